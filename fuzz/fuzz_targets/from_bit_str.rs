@@ -0,0 +1,12 @@
+#![no_main]
+
+use imagehash::Hash;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &str| {
+    if let Ok(hash) = Hash::from_bit_str(data) {
+        let text = hash.to_binary_string();
+        let parsed = Hash::from_bit_str(&text).unwrap();
+        assert_eq!(parsed.bits, hash.bits);
+    }
+});
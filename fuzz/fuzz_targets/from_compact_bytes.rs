@@ -0,0 +1,12 @@
+#![no_main]
+
+use imagehash::Hash;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(hash) = Hash::from_compact_bytes(data) {
+        let bytes = hash.to_compact_bytes();
+        let parsed = Hash::from_compact_bytes(&bytes).unwrap();
+        assert_eq!(parsed.bits, hash.bits);
+    }
+});
@@ -41,6 +41,13 @@
 //! let hash = hasher.hash(&img);
 //! println!("{}", hash); // hex-encoded hash string
 //! ```
+//!
+//! ## Features
+//!
+//! - `rayon`: Parallelizes scans over multiple hashes.
+//! - `bitvec`: Adds conversions between `Hash` and `bitvec::vec::BitVec`.
+//! - `fixed-dct`: Adds `TransformKind::FixedDct2`, a fixed-point DCT for pHash.
+//! - `jpeg`: Enables JPEG decoding, reduced-size decoding of JPEG files in `open_jpeg_scaled`, and decoding of CMYK JPEG files to their ink values for `ColorSpace::Cmyk`.
 
 /// Represents a hash value.
 #[derive(Debug)]
@@ -50,6 +57,16 @@ pub struct Hash {
 }
 
 impl Hash {
+    /// Returns the bit slice representation of the hash.
+    pub fn as_bits(&self) -> &[bool] {
+        &self.bits
+    }
+
+    /// Consumes the hash and returns the bit vector representation.
+    pub fn into_bits(self) -> Vec<bool> {
+        self.bits
+    }
+
     /// Returns the byte vector representation of the hash.
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = vec![0; (self.bits.len() + 7) / 8];
@@ -60,323 +77,4591 @@ impl Hash {
         }
         bytes
     }
-}
 
-impl From<Vec<bool>> for Hash {
-    fn from(bits: Vec<bool>) -> Self {
-        Hash { bits }
+    /// Returns the byte vector representation of the hash padded with zero bytes to `len` bytes.
+    ///
+    /// Returns `HashError::TooLong` if `to_bytes` returns more than `len` bytes.
+    pub fn to_bytes_fixed(&self, len: usize) -> Result<Vec<u8>, HashError> {
+        let mut bytes = self.to_bytes();
+        if bytes.len() > len {
+            return Err(HashError::TooLong(bytes.len(), len));
+        }
+        bytes.resize(len, 0);
+        Ok(bytes)
     }
-}
 
-impl std::fmt::Display for Hash {
-    /// Returns the hex-encoded string representation of the hash.
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        let bytes = self.to_bytes();
-        let mut result = String::with_capacity(bytes.len() * 2);
-        for byte in bytes {
-            result.push_str(&format!("{:02x}", byte));
+    /// Returns the hash as an integer, or `None` if the hash is longer than 128 bits.
+    ///
+    /// The first bit is the most significant bit of the value.
+    pub fn to_u128(&self) -> Option<u128> {
+        if self.bits.len() > 128 {
+            return None;
         }
-        write!(f, "{}", result)
+        Some(
+            self.bits
+                .iter()
+                .fold(0, |acc, &bit| (acc << 1) | u128::from(bit)),
+        )
     }
-}
-
-/// Represents a grayscale image.
-struct GrayscaleImage {
-    pixels: Vec<u8>,
-    width: usize,
-    height: usize,
-}
 
-impl GrayscaleImage {
-    /// Creates a new `GrayscaleImage` from the flattened pixels.
-    fn new(pixels: Vec<u8>, width: usize, height: usize) -> Self {
-        assert_eq!(pixels.len(), width * height);
-        GrayscaleImage {
-            pixels,
-            width,
-            height,
+    /// Creates a hash from the lowest `bit_len` bits of the integer, or `None` if `bit_len` exceeds 128.
+    ///
+    /// This is the inverse of `to_u128`.
+    pub fn from_u128(value: u128, bit_len: usize) -> Option<Hash> {
+        if bit_len > 128 {
+            return None;
         }
+        Some((0..bit_len).rev().map(|i| (value >> i) & 1 == 1).collect())
     }
 
-    /// Returns an iterator over the pixels as the specified type.
-    fn iter_pixels_as<'a, T>(&'a self) -> impl Iterator<Item = T> + 'a
-    where
-        T: From<u8> + 'a,
-    {
-        self.pixels.iter().map(|&v| T::from(v))
+    /// Returns the string of `'0'` and `'1'` characters representing the bits.
+    pub fn to_binary_string(&self) -> String {
+        self.bits
+            .iter()
+            .map(|&bit| if bit { '1' } else { '0' })
+            .collect()
     }
 
-    /// Returns an iterator over the rows as the specified type.
-    fn iter_rows_as<'a, T>(&'a self) -> impl Iterator<Item = impl Iterator<Item = T> + 'a> + 'a
-    where
-        T: From<u8> + 'a,
-    {
-        self.pixels
-            .chunks(self.width)
-            .map(|row| row.iter().map(|&v| T::from(v)))
+    /// Returns the hex string of the hash as formatted by the Python imagehash library.
+    ///
+    /// The bits are in the same row-major order as the Python library, but it formats them as an
+    /// integer of `ceil(bit_len / 4)` hex digits with the bits aligned to the end. This agrees with
+    /// `Display` when the bit length is a multiple of 8, and `load_hashes` parses it back.
+    pub fn to_python_hex(&self) -> String {
+        let padding = (4 - self.bits.len() % 4) % 4;
+        let bits: Vec<bool> = std::iter::repeat(false)
+            .take(padding)
+            .chain(self.bits.iter().copied())
+            .collect();
+        bits.chunks(4)
+            .map(|nibble| {
+                let value = nibble
+                    .iter()
+                    .fold(0, |acc, &bit| (acc << 1) | u32::from(bit));
+                std::char::from_digit(value, 16).unwrap()
+            })
+            .collect()
     }
-}
 
-impl From<image::DynamicImage> for GrayscaleImage {
-    fn from(image: image::DynamicImage) -> Self {
-        let width = image.width() as usize;
-        let height = image.height() as usize;
-        GrayscaleImage::new(image.into_luma8().into_raw(), width, height)
+    /// Creates a hash from a string of `'0'` and `'1'` characters, ignoring whitespace.
+    ///
+    /// This is the inverse of `to_binary_string`.
+    pub fn from_bit_str(s: &str) -> Result<Hash, ParseHashError> {
+        s.chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| match c {
+                '0' => Ok(false),
+                '1' => Ok(true),
+                _ => Err(ParseHashError::InvalidCharacter(c)),
+            })
+            .collect()
     }
-}
 
-fn resize(image: &image::DynamicImage, width: usize, height: usize) -> image::DynamicImage {
-    image.resize_exact(
-        width as u32,
-        height as u32,
-        image::imageops::FilterType::Lanczos3,
-    )
-}
+    /// Returns the compact binary representation of the hash.
+    ///
+    /// The bit length is encoded as an unsigned LEB128 varint, followed by the bytes of `to_bytes`.
+    pub fn to_compact_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.bits.len() / 8 + 2);
+        let mut len = self.bits.len();
+        loop {
+            let byte = (len & 0x7f) as u8;
+            len >>= 7;
+            if len == 0 {
+                bytes.push(byte);
+                break;
+            }
+            bytes.push(byte | 0x80);
+        }
+        bytes.extend(self.to_bytes());
+        bytes
+    }
 
-/// Provides average hash (aHash) calculation.
-pub struct AverageHash {
-    image_size: (usize, usize),
-    hash_size: (usize, usize),
-    resizer: fn(&image::DynamicImage, usize, usize) -> image::DynamicImage,
-}
+    /// Creates a hash from the compact binary representation returned by `to_compact_bytes`.
+    pub fn from_compact_bytes(bytes: &[u8]) -> Result<Hash, ParseHashError> {
+        let mut len: usize = 0;
+        let mut offset = 0;
+        loop {
+            let byte = *bytes.get(offset).ok_or(ParseHashError::Truncated)?;
+            let value = usize::from(byte & 0x7f);
+            let shift = 7 * offset as u32;
+            if shift >= usize::BITS || (value << shift) >> shift != value {
+                return Err(ParseHashError::InvalidLength);
+            }
+            len |= value << shift;
+            offset += 1;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        let data = &bytes[offset..];
+        let expected = len / 8 + usize::from(len % 8 != 0);
+        if data.len() < expected {
+            return Err(ParseHashError::Truncated);
+        }
+        if data.len() > expected {
+            return Err(ParseHashError::TrailingBytes);
+        }
+        Ok((0..len)
+            .map(|i| data[i / 8] & (1 << (7 - (i % 8))) != 0)
+            .collect())
+    }
 
-impl AverageHash {
-    /// Creates a new `AverageHasher` with default parameters.
-    pub fn new() -> Self {
-        AverageHash::default()
+    /// Renders the hash as a grayscale image with white pixels for set bits and black pixels for unset bits.
+    ///
+    /// Returns `None` if `width * height` differs from the bit length.
+    pub fn to_image(&self, width: usize, height: usize) -> Option<image::GrayImage> {
+        if width * height != self.bits.len() {
+            return None;
+        }
+        let pixels = self
+            .bits
+            .iter()
+            .map(|&bit| if bit { 255 } else { 0 })
+            .collect();
+        image::GrayImage::from_raw(width as u32, height as u32, pixels)
     }
 
-    /// Constructs a hasher with the image size.
-    pub fn with_image_size(self, width: usize, height: usize) -> Self {
-        AverageHash {
-            image_size: (width, height),
-            ..self
+    /// Returns a description of the hash including its bit length, such as `64-bit: 00007cf0e0eafefe`.
+    ///
+    /// This is intended for logging and debugging; use `Display` for storage.
+    pub fn describe(&self) -> String {
+        format!("{}-bit: {}", self.bits.len(), self)
+    }
+
+    /// Returns the hash inverted if more than half of its bits are set, and a copy of it otherwise.
+    ///
+    /// Distances between canonicalized hashes are invariant to global inversions. This only makes
+    /// sense for hashes whose overall sign is meaningless, such as those of sign-ambiguous transform
+    /// coefficients; for aHash and dHash an inversion is a real difference. Hashes with exactly half
+    /// of their bits set are not inverted, so a hash and its inversion may still differ.
+    pub fn canonicalize(&self) -> Hash {
+        let set = self.bits.iter().filter(|&&bit| bit).count();
+        if 2 * set > self.bits.len() {
+            self.bits.iter().map(|&bit| !bit).collect()
+        } else {
+            Hash {
+                bits: self.bits.clone(),
+            }
         }
     }
 
-    /// Constructs a hasher with the hash size.
-    pub fn with_hash_size(self, width: usize, height: usize) -> Self {
-        AverageHash {
-            hash_size: (width, height),
-            ..self
+    /// Returns whether the hashes have the same bits, ignoring unset bits after the end of the shorter one.
+    ///
+    /// This treats a hash restored from padded bytes, such as with `to_bytes`, as equal to the original.
+    pub fn eq_ignoring_padding(&self, other: &Hash) -> bool {
+        let (shorter, longer) = if self.bits.len() <= other.bits.len() {
+            (&self.bits, &other.bits)
+        } else {
+            (&other.bits, &self.bits)
+        };
+        longer[..shorter.len()] == shorter[..] && longer[shorter.len()..].iter().all(|&bit| !bit)
+    }
+
+    /// Divides the hash into the bits before `index` and the bits from `index`.
+    ///
+    /// This extracts the components of a concatenated hash, such as the aHash and dHash of
+    /// `CompositeHash`, to compare them separately.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than the bit length.
+    pub fn split_at(&self, index: usize) -> (Hash, Hash) {
+        let (head, tail) = self.bits.split_at(index);
+        (
+            Hash {
+                bits: head.to_vec(),
+            },
+            Hash {
+                bits: tail.to_vec(),
+            },
+        )
+    }
+
+    /// Returns the hash of the bits at the indices, in the order of the indices.
+    ///
+    /// With the indices of bits found stable across a data set, the short signature is a cheap
+    /// first-pass filter before comparing the full hashes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an index is not less than the bit length.
+    pub fn select_bits(&self, indices: &[usize]) -> Hash {
+        indices.iter().map(|&i| self.bits[i]).collect()
+    }
+
+    /// Resamples the bit grid of `from` (width, height) to `to` with the nearest neighbor.
+    ///
+    /// This allows approximate comparisons between hashes of different hash sizes, such as
+    /// during a migration between them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `from.0 * from.1` is not the bit length.
+    pub fn resample(&self, from: (usize, usize), to: (usize, usize)) -> Hash {
+        assert_eq!(from.0 * from.1, self.bits.len());
+        let mut bits = Vec::with_capacity(to.0 * to.1);
+        for y in 0..to.1 {
+            let sy = (2 * y + 1) * from.1 / (2 * to.1);
+            for x in 0..to.0 {
+                let sx = (2 * x + 1) * from.0 / (2 * to.0);
+                bits.push(self.bits[sy * from.0 + sx]);
+            }
         }
+        Hash { bits }
     }
 
-    /// Constructs a hasher with the resizer function.
-    pub fn with_resizer(
+    /// Tags the hash with the algorithm and the hash size that produced it.
+    ///
+    /// Returns `HashError::LengthMismatch` if the hash does not have `width * height` bits, since
+    /// the tag would not parse back.
+    pub fn tagged(
         self,
-        resizer: fn(&image::DynamicImage, usize, usize) -> image::DynamicImage,
-    ) -> Self {
-        AverageHash { resizer, ..self }
+        algorithm: Algorithm,
+        width: usize,
+        height: usize,
+    ) -> Result<TaggedHash, HashError> {
+        if width.checked_mul(height) != Some(self.bits.len()) {
+            return Err(HashError::LengthMismatch(
+                self.bits.len(),
+                width.saturating_mul(height),
+            ));
+        }
+        Ok(TaggedHash {
+            algorithm,
+            width,
+            height,
+            hash: self,
+        })
     }
 
-    /// Calculates average hash (aHash) of the image and returns as a hex string.
-    pub fn hash(&self, image: &image::DynamicImage) -> Hash {
-        let image: GrayscaleImage =
-            (self.resizer)(&image.grayscale(), self.image_size.0, self.image_size.0).into();
-        average_hash_core(&image, self.hash_size.0, self.hash_size.1)
+    /// Returns the Hamming distance to the other hash.
+    ///
+    /// Returns `HashError::LengthMismatch` if the hashes have different bit lengths.
+    pub fn distance(&self, other: &Hash) -> Result<Distance, HashError> {
+        if self.bits.len() != other.bits.len() {
+            return Err(HashError::LengthMismatch(self.bits.len(), other.bits.len()));
+        }
+        Ok(Distance(bit_distance(&self.bits, &other.bits)))
     }
-}
 
-impl Default for AverageHash {
-    /// Creates a new `AverageHasher` with default parameters.
-    fn default() -> Self {
-        AverageHash {
-            image_size: (8, 8),
-            hash_size: (8, 8),
-            resizer: resize,
+    /// Returns the Hamming distances to the other hashes, in the order of the other hashes.
+    ///
+    /// The hash is packed into words once, and each other hash is packed on the fly without
+    /// allocating. Returns `HashError::LengthMismatch` for the first other hash with a different bit
+    /// length, checked before any distance is computed. The distances are computed in parallel when
+    /// the `rayon` feature is enabled.
+    pub fn distances(&self, others: &[Hash]) -> Result<Vec<Distance>, HashError> {
+        if let Some(other) = others.iter().find(|o| o.bits.len() != self.bits.len()) {
+            return Err(HashError::LengthMismatch(self.bits.len(), other.bits.len()));
         }
+        let packed = pack_words(&self.bits);
+        Ok(par_map(others, |other| {
+            Distance(
+                packed
+                    .iter()
+                    .zip(other.bits.chunks(64))
+                    .map(|(word, chunk)| (word ^ pack_word(chunk)).count_ones() as usize)
+                    .sum(),
+            )
+        }))
     }
-}
 
-/// Calculates average hash (aHash) of the image.
-pub fn average_hash(image: &image::DynamicImage) -> Hash {
-    let image: GrayscaleImage = resize(&image.grayscale(), 8, 8).into();
-    average_hash_core(&image, 8, 8)
+    /// Returns the Jaccard similarity of the set bits, the number of bits set in both hashes divided
+    /// by the number of bits set in either.
+    ///
+    /// The similarity is 1.0 if neither hash has set bits. Returns `HashError::LengthMismatch` if the
+    /// hashes have different bit lengths.
+    pub fn jaccard(&self, other: &Hash) -> Result<f64, HashError> {
+        if self.bits.len() != other.bits.len() {
+            return Err(HashError::LengthMismatch(self.bits.len(), other.bits.len()));
+        }
+        let (mut intersection, mut union) = (0, 0);
+        for (&a, &b) in self.bits.iter().zip(&other.bits) {
+            intersection += usize::from(a && b);
+            union += usize::from(a || b);
+        }
+        if union == 0 {
+            return Ok(1.0);
+        }
+        Ok(intersection as f64 / union as f64)
+    }
 }
 
-fn average_hash_core(image: &GrayscaleImage, hash_width: usize, hash_height: usize) -> Hash {
-    let total: f64 = image
-        .iter_rows_as::<f64>()
-        .take(hash_height)
-        .flat_map(|row| row.take(hash_width))
-        .sum();
-    let mean = total / (hash_width * hash_height) as f64;
-    image
-        .iter_pixels_as::<f64>()
-        .map(|v| v > mean)
-        .collect::<Vec<bool>>()
-        .into()
-}
+/// Represents the Hamming distance between two hashes.
+///
+/// Distances between hashes of different bit lengths are not comparable without `normalized`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Distance(pub usize);
 
-/// Provides difference hash (dHash) calculation.
-pub struct DifferenceHash {
-    image_size: (usize, usize),
-    hash_size: (usize, usize),
-    resizer: fn(&image::DynamicImage, usize, usize) -> image::DynamicImage,
+impl Distance {
+    /// Returns the distance as a fraction of the bit length, from 0.0 to 1.0.
+    pub fn normalized(self, bit_len: usize) -> f64 {
+        self.0 as f64 / bit_len as f64
+    }
 }
 
-impl DifferenceHash {
-    /// Creates a new `DifferenceHasher` with default parameters.
-    pub fn new() -> Self {
-        DifferenceHash::default()
+impl std::fmt::Display for Distance {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.0)
     }
+}
 
-    /// Constructs a hasher with the image size.
-    pub fn with_image_size(self, width: usize, height: usize) -> Self {
-        DifferenceHash {
-            image_size: (width, height),
-            ..self
-        }
+#[cfg(feature = "bitvec")]
+impl Hash {
+    /// Returns the bits as a `BitVec`, with the first bit at index 0.
+    pub fn to_bitvec(&self) -> bitvec::vec::BitVec {
+        self.bits.iter().collect()
     }
+}
 
-    /// Constructs a hasher with the hash size.
-    pub fn with_hash_size(self, width: usize, height: usize) -> Self {
-        DifferenceHash {
-            hash_size: (width, height),
-            ..self
+#[cfg(feature = "bitvec")]
+impl<T: bitvec::store::BitStore, O: bitvec::order::BitOrder> From<bitvec::vec::BitVec<T, O>>
+    for Hash
+{
+    fn from(bits: bitvec::vec::BitVec<T, O>) -> Self {
+        Hash {
+            bits: bits.iter().by_vals().collect(),
         }
     }
+}
 
-    /// Constructs a hasher with the resizer function.
-    pub fn with_resizer(
-        self,
-        resizer: fn(&image::DynamicImage, usize, usize) -> image::DynamicImage,
-    ) -> Self {
-        DifferenceHash { resizer, ..self }
-    }
-
-    /// Calculates difference hash (dHash) of the image and returns as a hex string.
-    pub fn hash(&self, image: &image::DynamicImage) -> Hash {
-        let image: GrayscaleImage =
-            (self.resizer)(&image.grayscale(), self.image_size.0, self.image_size.1).into();
-        difference_hash_core(&image, self.hash_size.0, self.hash_size.1)
+impl From<Vec<bool>> for Hash {
+    fn from(bits: Vec<bool>) -> Self {
+        Hash { bits }
     }
 }
 
-impl Default for DifferenceHash {
-    /// Creates a new `DifferenceHasher` with default parameters.
-    fn default() -> Self {
-        DifferenceHash {
-            image_size: (9, 8),
-            hash_size: (8, 8),
-            resizer: resize,
+impl FromIterator<bool> for Hash {
+    fn from_iter<I: IntoIterator<Item = bool>>(iter: I) -> Self {
+        Hash {
+            bits: iter.into_iter().collect(),
         }
     }
 }
 
-/// Calculates difference hash (dHash) of the image.
-pub fn difference_hash(image: &image::DynamicImage) -> Hash {
-    let image: GrayscaleImage = resize(&image.grayscale(), 9, 8).into();
-    difference_hash_core(&image, 8, 8)
-}
-
-fn difference_hash_core(image: &GrayscaleImage, hash_width: usize, hash_height: usize) -> Hash {
-    image
-        .iter_rows_as::<u8>()
-        .take(hash_height)
-        .flat_map(|row| {
-            row.collect::<Vec<u8>>()
-                .windows(2)
-                .take(hash_width)
-                .map(|w| w[1] > w[0])
-                .collect::<Vec<bool>>()
-        })
-        .collect::<Vec<bool>>()
-        .into()
+impl std::fmt::Display for Hash {
+    /// Returns the hex-encoded string representation of the hash.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let bytes = self.to_bytes();
+        let mut result = String::with_capacity(bytes.len() * 2);
+        for byte in bytes {
+            result.push_str(&format!("{:02x}", byte));
+        }
+        write!(f, "{}", result)
+    }
 }
 
-/// Provides perceptual hash (pHash) calculation.
-pub struct PerceptualHash {
-    image_size: (usize, usize),
-    hash_size: (usize, usize),
-    resizer: fn(&image::DynamicImage, usize, usize) -> image::DynamicImage,
+/// Provides rendering of hashes as hex strings with a prefix and a separator between bytes.
+///
+/// The bytes are those of `to_bytes`, so with the default parameters this agrees with `Display`,
+/// which stays the canonical format for storage.
+#[derive(Debug, Clone, Default)]
+pub struct HashFormat {
+    prefix: String,
+    byte_separator: String,
 }
 
-impl PerceptualHash {
-    /// Creates a new `PerceptualHasher` with default parameters.
+impl HashFormat {
+    /// Creates a new `HashFormat` with no prefix and no separator.
     pub fn new() -> Self {
-        PerceptualHash::default()
+        HashFormat::default()
     }
 
-    /// Constructs a hasher with the image size.
-    pub fn with_image_size(self, width: usize, height: usize) -> Self {
-        PerceptualHash {
-            image_size: (width, height),
+    /// Constructs a format with the prefix, such as `"0x"`.
+    pub fn with_prefix(self, prefix: &str) -> Self {
+        HashFormat {
+            prefix: prefix.to_string(),
             ..self
         }
     }
 
-    /// Constructs a hasher with the hash size.
-    pub fn with_hash_size(self, width: usize, height: usize) -> Self {
-        PerceptualHash {
-            hash_size: (width, height),
+    /// Constructs a format with the separator between bytes, such as `":"`.
+    pub fn with_byte_separator(self, byte_separator: &str) -> Self {
+        HashFormat {
+            byte_separator: byte_separator.to_string(),
             ..self
         }
     }
 
-    /// Constructs a hasher with the resizer function.
-    pub fn with_resizer(
-        self,
-        resizer: fn(&image::DynamicImage, usize, usize) -> image::DynamicImage,
-    ) -> Self {
-        PerceptualHash { resizer, ..self }
+    /// Returns the hex string of the hash in this format.
+    pub fn format(&self, hash: &Hash) -> String {
+        let bytes: Vec<String> = hash
+            .to_bytes()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        format!("{}{}", self.prefix, bytes.join(&self.byte_separator))
     }
+}
 
-    /// Calculates perceptual hash (pHash) of the image and returns as a hex string.
-    pub fn hash(&self, image: &image::DynamicImage) -> Hash {
-        let image: GrayscaleImage =
-            (self.resizer)(&image.grayscale(), self.image_size.0, self.image_size.1).into();
-        perceptual_hash_core(&image, self.hash_size.0, self.hash_size.1)
+/// Represents a hash value of `W` x `H` bits stored inline without heap allocation.
+///
+/// This is produced by `AverageHashN` and converts into `Hash` for the other helpers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HashN<const W: usize, const H: usize> {
+    bits: [[bool; W]; H],
+}
+
+impl<const W: usize, const H: usize> HashN<W, H> {
+    /// Returns the rows of bits.
+    pub fn bits(&self) -> &[[bool; W]; H] {
+        &self.bits
+    }
+
+    /// Returns the Hamming distance to the other hash.
+    pub fn distance(&self, other: &HashN<W, H>) -> Distance {
+        Distance(
+            self.bits
+                .iter()
+                .flatten()
+                .zip(other.bits.iter().flatten())
+                .filter(|(a, b)| a != b)
+                .count(),
+        )
     }
 }
 
-impl Default for PerceptualHash {
-    /// Creates a new `PerceptualHasher` with default parameters.
-    fn default() -> Self {
-        PerceptualHash {
-            image_size: (32, 32),
-            hash_size: (8, 8),
-            resizer: resize,
+impl<const W: usize, const H: usize> From<HashN<W, H>> for Hash {
+    fn from(hash: HashN<W, H>) -> Self {
+        Hash {
+            bits: hash.bits.iter().flatten().copied().collect(),
         }
     }
 }
 
-/// Calculates perceptual hash (pHash) of the image.
-pub fn perceptual_hash(image: &image::DynamicImage) -> Hash {
-    let image: GrayscaleImage = resize(&image.grayscale(), 32, 32).into();
-    perceptual_hash_core(&image, 8, 8)
+impl<const W: usize, const H: usize> std::fmt::Display for HashN<W, H> {
+    /// Returns the hex-encoded string representation of the hash, which equals that of `Hash`.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let (mut byte, mut len) = (0u8, 0);
+        for &bit in self.bits.iter().flatten() {
+            byte = (byte << 1) | u8::from(bit);
+            len += 1;
+            if len == 8 {
+                write!(f, "{:02x}", byte)?;
+                byte = 0;
+                len = 0;
+            }
+        }
+        if len > 0 {
+            write!(f, "{:02x}", byte << (8 - len))?;
+        }
+        Ok(())
+    }
 }
 
-fn perceptual_hash_core(image: &GrayscaleImage, hash_width: usize, hash_height: usize) -> Hash {
-    let mut dct_rows = vec![0.0; image.width * image.height];
-    for (y, row) in image.iter_rows_as::<f64>().enumerate() {
-        let dct = dct2(&row.collect::<Vec<_>>());
-        for (x, v) in dct.iter().enumerate() {
-            dct_rows[y * image.width + x] = *v;
+/// Counts the hashes in `haystack` within `max_distance` of `query`.
+///
+/// Hashes with a bit length different from `query` are never counted.
+/// The scan runs in parallel when the `rayon` feature is enabled.
+pub fn count_within(query: &Hash, haystack: &[Hash], max_distance: Distance) -> usize {
+    par_map(
+        haystack,
+        |hash| matches!(query.distance(hash), Ok(d) if d <= max_distance),
+    )
+    .into_iter()
+    .filter(|&within| within)
+    .count()
+}
+
+/// Returns the index and distance of the candidate closest to `query`, or `None` if there is no comparable candidate.
+///
+/// Candidates with a bit length different from `query` are skipped, and ties resolve to the lowest index.
+/// The scan runs in parallel when the `rayon` feature is enabled.
+pub fn best_match(query: &Hash, candidates: &[Hash]) -> Option<(usize, Distance)> {
+    par_map(candidates, |hash| query.distance(hash).ok())
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, d)| d.map(|d| (i, d)))
+        .min_by_key(|&(i, d)| (d, i))
+}
+
+/// Hashes the query image and returns the `top_n` entries of the corpus closest to it, with their distances.
+///
+/// The matches are sorted by ascending distance, and ties keep their order in the corpus.
+/// Entries with a bit length different from the query hash are skipped.
+/// The distances are computed in parallel when the `rayon` feature is enabled.
+pub fn search(
+    query: &image::DynamicImage,
+    corpus: &[(std::path::PathBuf, Hash)],
+    hasher: &dyn ImageHasher,
+    top_n: usize,
+) -> Vec<(std::path::PathBuf, Distance)> {
+    let query = hasher.hash(query);
+    let mut matches: Vec<(Distance, usize)> =
+        par_map(corpus, |(_, hash)| query.distance(hash).ok())
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, d)| d.map(|d| (d, i)))
+            .collect();
+    matches.sort_unstable();
+    matches
+        .into_iter()
+        .take(top_n)
+        .map(|(d, i)| (corpus[i].0.clone(), d))
+        .collect()
+}
+
+/// Hashes the images with the hasher, returning the hashes in the order of the images.
+///
+/// The images are hashed in parallel when the `rayon` feature is enabled. Each image is hashed on a
+/// single thread, so the hashes are identical to those of hashing the images one by one.
+pub fn hash_batch<H: ImageHasher + Sync>(hasher: &H, images: &[image::DynamicImage]) -> Vec<Hash> {
+    par_map(images, |image| hasher.hash(image))
+}
+
+/// Groups the hashes into clusters connected by pairs within `max_distance` of each other.
+///
+/// Each cluster lists the indices of its hashes in ascending order, and the clusters are ordered by
+/// their first index. Hashes with different bit lengths are never connected.
+/// The pairwise comparison runs in parallel when the `rayon` feature is enabled.
+pub fn cluster(hashes: &[Hash], max_distance: Distance) -> Vec<Vec<usize>> {
+    let packed: Vec<Vec<u64>> = hashes.iter().map(|hash| pack_words(&hash.bits)).collect();
+    let indices: Vec<usize> = (0..hashes.len()).collect();
+    let neighbors: Vec<Vec<usize>> = par_map(&indices, |&i| {
+        (i + 1..hashes.len())
+            .filter(|&j| {
+                hashes[i].bits.len() == hashes[j].bits.len()
+                    && packed_distance(&packed[i], &packed[j]) <= max_distance.0
+            })
+            .collect()
+    });
+
+    let mut parents: Vec<usize> = (0..hashes.len()).collect();
+    for (i, js) in neighbors.iter().enumerate() {
+        for &j in js {
+            let (a, b) = (find_root(&mut parents, i), find_root(&mut parents, j));
+            parents[a.max(b)] = a.min(b);
         }
     }
-    let low_freqs: Vec<f64> = dct_rows
-        .chunks(image.width)
-        .take(hash_height)
-        .flat_map(|row| {
-            row.iter()
-                .skip(1)
-                .take(hash_width)
-                .copied()
-                .collect::<Vec<_>>()
-        })
-        .collect();
-    let mean = low_freqs.iter().sum::<f64>() / (hash_width * hash_height) as f64;
-    low_freqs
-        .iter()
-        .map(|v| *v > mean)
-        .collect::<Vec<bool>>()
-        .into()
+
+    let mut clusters: Vec<Vec<usize>> = Vec::new();
+    let mut cluster_of_root = vec![usize::MAX; hashes.len()];
+    for i in 0..hashes.len() {
+        let root = find_root(&mut parents, i);
+        if cluster_of_root[root] == usize::MAX {
+            cluster_of_root[root] = clusters.len();
+            clusters.push(Vec::new());
+        }
+        clusters[cluster_of_root[root]].push(i);
+    }
+    clusters
 }
 
-fn dct2(input: &[f64]) -> Vec<f64> {
+/// Merges two hash indexes, collapsing clusters of near-duplicates into one representative.
+///
+/// The hashes are clustered with `cluster`, and each cluster keeps its first hash, so entries of
+/// `a` are preferred over entries of `b`. The representatives are returned in the order of `a`
+/// followed by `b`.
+pub fn merge_indexes(a: Vec<Hash>, b: Vec<Hash>, max_distance: Distance) -> Vec<Hash> {
+    let mut hashes = a;
+    hashes.extend(b);
+    let mut keep = vec![false; hashes.len()];
+    for members in cluster(&hashes, max_distance) {
+        keep[members[0]] = true;
+    }
+    hashes
+        .into_iter()
+        .zip(keep)
+        .filter_map(|(hash, keep)| if keep { Some(hash) } else { None })
+        .collect()
+}
+
+/// Maps the items in order, in parallel when the `rayon` feature is enabled.
+#[cfg(feature = "rayon")]
+fn par_map<T: Sync, R: Send>(items: &[T], f: impl Fn(&T) -> R + Sync + Send) -> Vec<R> {
+    use rayon::prelude::*;
+    items.par_iter().map(f).collect()
+}
+
+/// Maps the items in order, in parallel when the `rayon` feature is enabled.
+#[cfg(not(feature = "rayon"))]
+fn par_map<T, R>(items: &[T], f: impl Fn(&T) -> R) -> Vec<R> {
+    items.iter().map(f).collect()
+}
+
+/// Packs up to 64 bits into a word, with the first bit as the most significant bit.
+fn pack_word(bits: &[bool]) -> u64 {
+    bits.iter()
+        .fold(0, |acc: u64, &bit| (acc << 1) | u64::from(bit))
+}
+
+/// Packs the bits into 64-bit words, with the first bit as the most significant bit of the first word.
+///
+/// Scans that compare each hash many times pack them once up front and compare the words with
+/// `packed_distance`.
+fn pack_words(bits: &[bool]) -> Vec<u64> {
+    bits.chunks(64).map(pack_word).collect()
+}
+
+/// Returns the Hamming distance between bit slices of the same length by XOR and popcount.
+///
+/// The bits are packed 64 at a time on the fly, so this does not allocate.
+fn bit_distance(a: &[bool], b: &[bool]) -> usize {
+    a.chunks(64)
+        .zip(b.chunks(64))
+        .map(|(x, y)| (pack_word(x) ^ pack_word(y)).count_ones() as usize)
+        .sum()
+}
+
+/// Returns the Hamming distance between the packed words of hashes with the same bit length.
+fn packed_distance(a: &[u64], b: &[u64]) -> usize {
+    a.iter()
+        .zip(b)
+        .map(|(x, y)| (x ^ y).count_ones() as usize)
+        .sum()
+}
+
+/// Returns the root of `i` in the union-find forest, compressing the path by halving.
+fn find_root(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+/// Returns the normalized cross-correlation of the hashes laid out as `width` x `height` grids.
+///
+/// Each grid is smoothed with a 3x3 box filter first, so clustered differences lower the correlation
+/// more than the same number of scattered differences. The result ranges from -1.0 to 1.0.
+/// Returns `HashError::LengthMismatch` if a hash does not have `width * height` bits.
+pub fn correlation(a: &Hash, b: &Hash, width: usize, height: usize) -> Result<f64, HashError> {
+    for hash in [a, b] {
+        if hash.bits.len() != width * height {
+            return Err(HashError::LengthMismatch(width * height, hash.bits.len()));
+        }
+    }
+    let a = smooth_bits(&a.bits, width, height);
+    let b = smooth_bits(&b.bits, width, height);
+    let n = a.len() as f64;
+    let mean_a = a.iter().sum::<f64>() / n;
+    let mean_b = b.iter().sum::<f64>() / n;
+    let (mut cov, mut var_a, mut var_b) = (0.0, 0.0, 0.0);
+    for (x, y) in a.iter().zip(b.iter()) {
+        cov += (x - mean_a) * (y - mean_b);
+        var_a += (x - mean_a) * (x - mean_a);
+        var_b += (y - mean_b) * (y - mean_b);
+    }
+    if var_a == 0.0 || var_b == 0.0 {
+        return Ok(if a == b { 1.0 } else { 0.0 });
+    }
+    Ok(cov / (var_a * var_b).sqrt())
+}
+
+/// Smooths the bit grid with a 3x3 box filter, averaging over the neighbors inside the grid.
+fn smooth_bits(bits: &[bool], width: usize, height: usize) -> Vec<f64> {
+    let mut result = Vec::with_capacity(bits.len());
+    for y in 0..height {
+        for x in 0..width {
+            let (mut sum, mut count) = (0.0, 0.0);
+            for ny in y.saturating_sub(1)..(y + 2).min(height) {
+                for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                    if bits[ny * width + nx] {
+                        sum += 1.0;
+                    }
+                    count += 1.0;
+                }
+            }
+            result.push(sum / count);
+        }
+    }
+    result
+}
+
+/// Returns whether the hashes are within each of the thresholds, computing their distance once.
+///
+/// Returns `HashError::LengthMismatch` if the hashes have different bit lengths.
+pub fn tiered_match(a: &Hash, b: &Hash, thresholds: &[Distance]) -> Result<Vec<bool>, HashError> {
+    let d = a.distance(b)?;
+    Ok(thresholds.iter().map(|&threshold| d <= threshold).collect())
+}
+
+/// Returns the indices of the bits that differ between the hashes, in ascending order.
+///
+/// Returns `HashError::LengthMismatch` if the hashes have different bit lengths.
+pub fn changed_bits(a: &Hash, b: &Hash) -> Result<Vec<usize>, HashError> {
+    if a.bits.len() != b.bits.len() {
+        return Err(HashError::LengthMismatch(a.bits.len(), b.bits.len()));
+    }
+    Ok(a.bits
+        .iter()
+        .zip(&b.bits)
+        .enumerate()
+        .filter(|(_, (x, y))| x != y)
+        .map(|(i, _)| i)
+        .collect())
+}
+
+/// Returns the bounding box of the differing bits of the hashes laid out as `width` x `height` rows.
+///
+/// The box is `(left, top, width, height)` in hash-grid coordinates, or `None` if the hashes are identical.
+/// Returns `HashError::LengthMismatch` if the hashes have different bit lengths, or if the bit length is
+/// not `width * height`.
+pub fn diff_bbox(
+    a: &Hash,
+    b: &Hash,
+    width: usize,
+    height: usize,
+) -> Result<Option<(usize, usize, usize, usize)>, HashError> {
+    let changed = changed_bits(a, b)?;
+    if a.bits.len() != width * height {
+        return Err(HashError::LengthMismatch(a.bits.len(), width * height));
+    }
+    let mut bbox: Option<(usize, usize, usize, usize)> = None;
+    for i in changed {
+        let (x, y) = (i % width, i / width);
+        bbox = Some(match bbox {
+            None => (x, y, x, y),
+            Some((left, top, right, bottom)) => {
+                (left.min(x), top.min(y), right.max(x), bottom.max(y))
+            }
+        });
+    }
+    Ok(bbox.map(|(left, top, right, bottom)| (left, top, right - left + 1, bottom - top + 1)))
+}
+
+/// Returns the number of pairs at each distance, indexed by distance up to the largest one.
+///
+/// Returns `HashError::LengthMismatch` if the hashes of a pair have different bit lengths.
+pub fn distance_histogram(pairs: &[(Hash, Hash)]) -> Result<Vec<usize>, HashError> {
+    let mut histogram = Vec::new();
+    for (a, b) in pairs {
+        let d = a.distance(b)?.0;
+        if histogram.len() <= d {
+            histogram.resize(d + 1, 0);
+        }
+        histogram[d] += 1;
+    }
+    Ok(histogram)
+}
+
+/// Returns the probability that two independent uniformly random hashes of `bit_len` bits are within `max_distance`.
+///
+/// This is the lower tail of the binomial distribution with `bit_len` trials and probability 0.5,
+/// which estimates the false positive rate of a `max_distance` for unrelated images.
+pub fn false_positive_rate(bit_len: usize, max_distance: Distance) -> f64 {
+    let max_distance = max_distance.0;
+    if max_distance >= bit_len {
+        return 1.0;
+    }
+    let n = bit_len as f64;
+    let mut ln_binomial = 0.0;
+    let mut sum = 0.0;
+    for k in 0..=max_distance {
+        if k > 0 {
+            ln_binomial += (n - k as f64 + 1.0).ln() - (k as f64).ln();
+        }
+        sum += (ln_binomial - n * std::f64::consts::LN_2).exp();
+    }
+    sum.min(1.0)
+}
+
+/// Returns the `max_distance` maximizing the F1 score of matching the labeled pairs, and the score.
+///
+/// Each pair has a label which is `true` if the hashes should match. A pair is predicted to match
+/// if its distance is at most `max_distance`. Ties are broken by the smallest `max_distance`.
+/// Returns `HashError::Empty` if no pairs are given, or `HashError::LengthMismatch` if the hashes
+/// of a pair have different bit lengths.
+pub fn best_threshold(pairs: &[(Hash, Hash, bool)]) -> Result<(Distance, f64), HashError> {
+    if pairs.is_empty() {
+        return Err(HashError::Empty);
+    }
+    let mut matches = Vec::new();
+    let mut non_matches = Vec::new();
+    for (a, b, label) in pairs {
+        let d = a.distance(b)?.0;
+        if matches.len() <= d {
+            matches.resize(d + 1, 0);
+            non_matches.resize(d + 1, 0);
+        }
+        if *label {
+            matches[d] += 1;
+        } else {
+            non_matches[d] += 1;
+        }
+    }
+    let positives: usize = matches.iter().sum();
+    let mut best = (0, 0.0);
+    let mut true_positives = 0;
+    let mut false_positives = 0;
+    for d in 0..matches.len() {
+        true_positives += matches[d];
+        false_positives += non_matches[d];
+        let false_negatives = positives - true_positives;
+        let denominator = 2 * true_positives + false_positives + false_negatives;
+        let score = if denominator == 0 {
+            0.0
+        } else {
+            (2 * true_positives) as f64 / denominator as f64
+        };
+        if score > best.1 {
+            best = (d, score);
+        }
+    }
+    Ok((Distance(best.0), best.1))
+}
+
+/// Returns the weighted sum of the normalized distances between the corresponding hashes of `a` and `b`.
+///
+/// Each pair of hashes is typically produced by a different algorithm, such as aHash, dHash and pHash
+/// of the same images, and its distance is normalized by its bit length before weighting.
+/// Returns `HashError::Empty` if no hashes are given, or `HashError::LengthMismatch` if `a`, `b` and
+/// `weights` have different lengths or the hashes of a pair have different bit lengths.
+pub fn combined_distance(a: &[Hash], b: &[Hash], weights: &[f64]) -> Result<f64, HashError> {
+    if a.len() != b.len() {
+        return Err(HashError::LengthMismatch(a.len(), b.len()));
+    }
+    if a.len() != weights.len() {
+        return Err(HashError::LengthMismatch(a.len(), weights.len()));
+    }
+    if a.is_empty() {
+        return Err(HashError::Empty);
+    }
+    let mut total = 0.0;
+    for ((a, b), weight) in a.iter().zip(b).zip(weights) {
+        total += weight * a.distance(b)?.normalized(a.bits.len());
+    }
+    Ok(total)
+}
+
+/// Combines the hashes into a consensus hash by majority vote on each bit.
+///
+/// A bit is set if it is set in more than half of the hashes.
+/// Returns `HashError::Empty` if no hashes are given, or `HashError::LengthMismatch` if the hashes have different bit lengths.
+pub fn majority_hash(hashes: &[Hash]) -> Result<Hash, HashError> {
+    let first = hashes.first().ok_or(HashError::Empty)?;
+    let mut counts = vec![0; first.bits.len()];
+    for hash in hashes {
+        if hash.bits.len() != counts.len() {
+            return Err(HashError::LengthMismatch(counts.len(), hash.bits.len()));
+        }
+        for (count, &bit) in counts.iter_mut().zip(hash.bits.iter()) {
+            if bit {
+                *count += 1;
+            }
+        }
+    }
+    Ok(counts
+        .iter()
+        .map(|&count| count * 2 > hashes.len())
+        .collect())
+}
+
+/// Represents an error in hash calculation or comparison.
+#[derive(Debug)]
+pub enum HashError {
+    /// The hashes have different bit lengths.
+    LengthMismatch(usize, usize),
+    /// The image is too flat to be meaningfully hashed.
+    LowContrast,
+    /// The image could not be opened or decoded.
+    Decode(image::ImageError),
+    /// The image exceeds the decoding limits on its dimensions or allocations.
+    TooLarge,
+    /// No hashes were given.
+    Empty,
+    /// The resized image has a size other than the expected `(width, height)`.
+    UnexpectedSize((usize, usize), (usize, usize)),
+    /// The tagged hashes were produced by different algorithms or hash sizes.
+    TagMismatch,
+    /// The source image of `(width, height)` is smaller than the minimum source size.
+    SourceTooSmall(usize, usize),
+    /// A NaN or infinite value reached the threshold.
+    NonFinite,
+    /// The hash of the first number of bytes does not fit in the second number of bytes.
+    TooLong(usize, usize),
+    /// The weight matrix has the second number of values for the first number of selected coefficients.
+    WeightMismatch(usize, usize),
+}
+
+impl std::fmt::Display for HashError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            HashError::LengthMismatch(a, b) => {
+                write!(f, "hash lengths do not match: {} and {}", a, b)
+            }
+            HashError::LowContrast => write!(f, "image contrast is too low"),
+            HashError::Decode(e) => write!(f, "failed to decode image: {}", e),
+            HashError::TooLarge => write!(f, "image exceeds the decoding limits"),
+            HashError::Empty => write!(f, "no hashes were given"),
+            HashError::UnexpectedSize(expected, actual) => write!(
+                f,
+                "resized image is {}x{}, expected {}x{}",
+                actual.0, actual.1, expected.0, expected.1
+            ),
+            HashError::TagMismatch => write!(f, "hash tags do not match"),
+            HashError::SourceTooSmall(width, height) => {
+                write!(f, "source image is too small: {}x{}", width, height)
+            }
+            HashError::NonFinite => write!(f, "non-finite value reached the threshold"),
+            HashError::TooLong(len, width) => {
+                write!(f, "hash of {} bytes does not fit in {} bytes", len, width)
+            }
+            HashError::WeightMismatch(selected, weights) => write!(
+                f,
+                "weight matrix has {} values for {} selected coefficients",
+                weights, selected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for HashError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            HashError::Decode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<image::ImageError> for HashError {
+    fn from(e: image::ImageError) -> Self {
+        match e {
+            image::ImageError::Limits(_) => HashError::TooLarge,
+            e => HashError::Decode(e),
+        }
+    }
+}
+
+/// The default maximum width and height of images decoded by the path-based hash functions.
+pub const DEFAULT_MAX_DIMENSION: u32 = 16384;
+
+/// The default maximum number of bytes allocated for decoding an image by the path-based hash functions.
+pub const DEFAULT_MAX_ALLOC: u64 = 512 * 1024 * 1024;
+
+/// Returns the decoding limits used by the path-based hash functions.
+///
+/// The width and height are capped at `DEFAULT_MAX_DIMENSION` and the allocations at `DEFAULT_MAX_ALLOC`.
+pub fn default_limits() -> image::io::Limits {
+    let mut limits = image::io::Limits::default();
+    limits.max_image_width = Some(DEFAULT_MAX_DIMENSION);
+    limits.max_image_height = Some(DEFAULT_MAX_DIMENSION);
+    limits.max_alloc = Some(DEFAULT_MAX_ALLOC);
+    limits
+}
+
+/// Opens and decodes the image file within the limits, so untrusted files cannot exhaust memory.
+///
+/// The format is determined from the file extension like `image::open`, and must be enabled in the
+/// features of the `image` crate. Returns `HashError::TooLarge` if the image exceeds the limits, or
+/// `HashError::Decode` if it cannot be opened or decoded.
+pub fn open_image<P: AsRef<std::path::Path>>(
+    path: P,
+    limits: image::io::Limits,
+) -> Result<image::DynamicImage, HashError> {
+    let mut reader = image::io::Reader::open(path).map_err(image::ImageError::IoError)?;
+    reader.limits(limits);
+    Ok(reader.decode()?)
+}
+
+/// The maximum number of bytes read from the start of a file to find its EXIF orientation.
+const EXIF_SEARCH_LEN: u64 = 256 * 1024;
+
+/// Rotates and flips the image decoded from the file upright by the EXIF orientation of the file.
+///
+/// Files without a readable orientation are returned unchanged.
+fn orient<P: AsRef<std::path::Path>>(image: image::DynamicImage, path: P) -> image::DynamicImage {
+    use std::io::Read;
+    let mut head = Vec::new();
+    let read = std::fs::File::open(path)
+        .and_then(|file| file.take(EXIF_SEARCH_LEN).read_to_end(&mut head));
+    match read
+        .ok()
+        .and_then(|_| exif_orientation(&head))
+        .and_then(Orientation::from_exif)
+    {
+        Some(Orientation::Normal) | None => image,
+        Some(orientation) => orientation.apply(&image),
+    }
+}
+
+/// Returns the orientation tag of the EXIF segment of the JPEG data, if any.
+///
+/// The segments are scanned up to the start of the scan, so only the head of a file is needed.
+fn exif_orientation(data: &[u8]) -> Option<u16> {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut i = 2;
+    while i + 4 <= data.len() {
+        if data[i] != 0xFF {
+            return None;
+        }
+        let marker = data[i + 1];
+        match marker {
+            0xFF => {
+                i += 1;
+                continue;
+            }
+            0x01 | 0xD0..=0xD8 => {
+                i += 2;
+                continue;
+            }
+            0xD9 | 0xDA => return None,
+            _ => {}
+        }
+        let len = usize::from(u16::from_be_bytes([data[i + 2], data[i + 3]]));
+        let segment = data.get(i + 4..i + 2 + len.max(2))?;
+        if marker == 0xE1 && segment.starts_with(b"Exif\0\0") {
+            return tiff_orientation(&segment[6..]);
+        }
+        i += 2 + len;
+    }
+    None
+}
+
+/// Returns the orientation tag (0x0112) of the first IFD of the TIFF data.
+fn tiff_orientation(tiff: &[u8]) -> Option<u16> {
+    let big_endian = if tiff.starts_with(b"MM") {
+        true
+    } else if tiff.starts_with(b"II") {
+        false
+    } else {
+        return None;
+    };
+    let u16_at = |offset: usize| {
+        let bytes = [*tiff.get(offset)?, *tiff.get(offset + 1)?];
+        Some(if big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    };
+    let u32_at = |offset: usize| {
+        let bytes = [
+            *tiff.get(offset)?,
+            *tiff.get(offset + 1)?,
+            *tiff.get(offset + 2)?,
+            *tiff.get(offset + 3)?,
+        ];
+        Some(if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    };
+    let ifd = u32_at(4)? as usize;
+    let count = usize::from(u16_at(ifd)?);
+    (0..count)
+        .map(|n| ifd + 2 + n * 12)
+        .find(|&entry| u16_at(entry) == Some(0x0112))
+        .and_then(|entry| u16_at(entry + 8))
+}
+
+/// Opens and decodes the image file within the limits, reducing JPEG files to about `width` x
+/// `height`.
+///
+/// With the `jpeg` feature, JPEG files are decoded at the smallest of 1/8, 1/4, 1/2 or the full
+/// scale covering `width` x `height`, which avoids decoding huge sources at full resolution. Only
+/// JPEG is reduced: other formats, including TIFF, are decoded at full resolution like `open_image`.
+/// The result is still to be resized to the image size, and its hashes may differ slightly from
+/// those of the full-resolution image.
+pub fn open_jpeg_scaled<P: AsRef<std::path::Path>>(
+    path: P,
+    width: usize,
+    height: usize,
+    limits: image::io::Limits,
+) -> Result<image::DynamicImage, HashError> {
+    #[cfg(feature = "jpeg")]
+    if image::ImageFormat::from_path(&path).ok() == Some(image::ImageFormat::Jpeg) {
+        use image::ImageDecoder;
+        let file = std::fs::File::open(path).map_err(image::ImageError::IoError)?;
+        let mut decoder = image::codecs::jpeg::JpegDecoder::new(std::io::BufReader::new(file))?;
+        let clamp = |v: usize| v.min(usize::from(u16::MAX)) as u16;
+        decoder.scale(clamp(width), clamp(height))?;
+        decoder.set_limits(limits)?;
+        return Ok(image::DynamicImage::from_decoder(decoder)?);
+    }
+    #[cfg(not(feature = "jpeg"))]
+    let _ = (width, height);
+    open_image(path, limits)
+}
+
+/// Represents an error in parsing a hash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseHashError {
+    /// The input ended before the hash was complete.
+    Truncated,
+    /// The input continues after the end of the hash.
+    TrailingBytes,
+    /// The encoded bit length is not representable.
+    InvalidLength,
+    /// The input contains a character that is not allowed.
+    InvalidCharacter(char),
+    /// The input is not in the expected format.
+    InvalidFormat,
+    /// The input could not be read.
+    Io(std::io::ErrorKind),
+}
+
+impl std::fmt::Display for ParseHashError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ParseHashError::Truncated => write!(f, "hash input is truncated"),
+            ParseHashError::TrailingBytes => write!(f, "hash input has trailing bytes"),
+            ParseHashError::InvalidLength => write!(f, "hash length is invalid"),
+            ParseHashError::InvalidCharacter(c) => {
+                write!(f, "hash input has an invalid character: {:?}", c)
+            }
+            ParseHashError::InvalidFormat => write!(f, "hash input has an invalid format"),
+            ParseHashError::Io(kind) => write!(f, "failed to read hash input: {:?}", kind),
+        }
+    }
+}
+
+impl std::error::Error for ParseHashError {}
+
+/// Loads hashes of `bit_len` bits from the reader, one hex string per line.
+///
+/// This reads indexes exported by the Python imagehash library, whose hex strings have
+/// `ceil(bit_len / 4)` digits with the bits aligned to the end. This agrees with `Display` when
+/// `bit_len` is a multiple of 8. Blank lines are skipped and whitespace around each hash is trimmed.
+pub fn load_hashes(
+    reader: impl std::io::BufRead,
+    bit_len: usize,
+) -> Result<Vec<Hash>, ParseHashError> {
+    let mut hashes = Vec::new();
+    for line in reader.lines() {
+        let line = line.map_err(|e| ParseHashError::Io(e.kind()))?;
+        let line = line.trim();
+        if !line.is_empty() {
+            hashes.push(parse_right_aligned_hex(line, bit_len)?);
+        }
+    }
+    Ok(hashes)
+}
+
+/// Parses the hex string of `ceil(bit_len / 4)` digits whose lowest `bit_len` bits are the hash.
+fn parse_right_aligned_hex(hex: &str, bit_len: usize) -> Result<Hash, ParseHashError> {
+    let nibbles = hex
+        .chars()
+        .map(|c| c.to_digit(16).ok_or(ParseHashError::InvalidCharacter(c)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let expected = bit_len / 4 + usize::from(bit_len % 4 != 0);
+    if nibbles.len() < expected {
+        return Err(ParseHashError::Truncated);
+    }
+    if nibbles.len() > expected {
+        return Err(ParseHashError::TrailingBytes);
+    }
+    let padding = expected * 4 - bit_len;
+    let bits: Vec<bool> = (0..expected * 4)
+        .map(|i| nibbles[i / 4] & (1 << (3 - (i % 4))) != 0)
+        .collect();
+    if bits[..padding].iter().any(|&bit| bit) {
+        return Err(ParseHashError::InvalidLength);
+    }
+    Ok(Hash {
+        bits: bits[padding..].to_vec(),
+    })
+}
+
+/// Represents a hash algorithm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    /// Average hash (aHash).
+    Average,
+    /// Difference hash (dHash).
+    Difference,
+    /// Perceptual hash (pHash).
+    Perceptual,
+}
+
+impl Algorithm {
+    fn tag(&self) -> &'static str {
+        match self {
+            Algorithm::Average => "ahash",
+            Algorithm::Difference => "dhash",
+            Algorithm::Perceptual => "phash",
+        }
+    }
+
+    fn from_tag(tag: &str) -> Option<Algorithm> {
+        match tag {
+            "ahash" => Some(Algorithm::Average),
+            "dhash" => Some(Algorithm::Difference),
+            "phash" => Some(Algorithm::Perceptual),
+            _ => None,
+        }
+    }
+}
+
+/// Represents a hash along with the algorithm and the hash size that produced it.
+///
+/// It is displayed and parsed as `<algorithm>:<width>x<height>:<hex>`, such as
+/// `phash:8x8:2f2fafafafafafaf`, where the algorithm is one of `ahash`, `dhash` and `phash`.
+#[derive(Debug)]
+pub struct TaggedHash {
+    /// The algorithm that produced the hash.
+    pub algorithm: Algorithm,
+    /// The width of the hash size.
+    pub width: usize,
+    /// The height of the hash size.
+    pub height: usize,
+    /// The hash.
+    pub hash: Hash,
+}
+
+impl TaggedHash {
+    /// Returns the Hamming distance to the other hash.
+    ///
+    /// Returns `HashError::TagMismatch` if the hashes have different algorithms or hash sizes.
+    pub fn distance(&self, other: &TaggedHash) -> Result<Distance, HashError> {
+        if self.algorithm != other.algorithm
+            || (self.width, self.height) != (other.width, other.height)
+        {
+            return Err(HashError::TagMismatch);
+        }
+        self.hash.distance(&other.hash)
+    }
+}
+
+impl std::fmt::Display for TaggedHash {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}x{}:{}",
+            self.algorithm.tag(),
+            self.width,
+            self.height,
+            self.hash
+        )
+    }
+}
+
+impl std::str::FromStr for TaggedHash {
+    type Err = ParseHashError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(3, ':');
+        let (tag, size, hex) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(tag), Some(size), Some(hex)) => (tag, size, hex),
+            _ => return Err(ParseHashError::InvalidFormat),
+        };
+        let algorithm = Algorithm::from_tag(tag).ok_or(ParseHashError::InvalidFormat)?;
+        let (width, height) = size
+            .split_once('x')
+            .and_then(|(w, h)| Some((w.parse::<usize>().ok()?, h.parse::<usize>().ok()?)))
+            .ok_or(ParseHashError::InvalidFormat)?;
+        let len = width
+            .checked_mul(height)
+            .ok_or(ParseHashError::InvalidLength)?;
+        let nibbles = hex
+            .chars()
+            .map(|c| c.to_digit(16).ok_or(ParseHashError::InvalidCharacter(c)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let expected = (len / 8 + usize::from(len % 8 != 0)) * 2;
+        if nibbles.len() < expected {
+            return Err(ParseHashError::Truncated);
+        }
+        if nibbles.len() > expected {
+            return Err(ParseHashError::TrailingBytes);
+        }
+        let hash = (0..len)
+            .map(|i| nibbles[i / 4] & (1 << (3 - (i % 4))) != 0)
+            .collect();
+        Ok(TaggedHash {
+            algorithm,
+            width,
+            height,
+            hash,
+        })
+    }
+}
+
+/// Calculates the hash of the image with the algorithm and its default parameters.
+pub fn hash_with(algorithm: Algorithm, image: &image::DynamicImage) -> Hash {
+    match algorithm {
+        Algorithm::Average => average_hash(image),
+        Algorithm::Difference => difference_hash(image),
+        Algorithm::Perceptual => perceptual_hash(image),
+    }
+}
+
+/// Represents a hasher that calculates hashes of images.
+pub trait ImageHasher {
+    /// Calculates the hash of the image.
+    fn hash(&self, image: &image::DynamicImage) -> Hash;
+
+    /// Calculates the hash of the grayscale image without preprocessing or resizing.
+    fn hash_gray(&self, image: &GrayscaleImage) -> Hash;
+
+    /// Calculates the hashes of the `window` (width, height) regions of the image at every `stride` step.
+    ///
+    /// Each hash is returned with the left and top of its window, in row-major order. Windows that
+    /// would extend past the image are skipped, so an image smaller than the window has no hashes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a side of `stride` is 0.
+    fn sliding_hashes(
+        &self,
+        image: &image::DynamicImage,
+        window: (u32, u32),
+        stride: (u32, u32),
+    ) -> Vec<((u32, u32), Hash)> {
+        assert!(stride.0 > 0 && stride.1 > 0, "stride must be positive");
+        let mut hashes = Vec::new();
+        let mut y = 0;
+        while y + window.1 <= image.height() {
+            let mut x = 0;
+            while x + window.0 <= image.width() {
+                let region = image.crop_imm(x, y, window.0, window.1);
+                hashes.push(((x, y), self.hash(&region)));
+                x += stride.0;
+            }
+            y += stride.1;
+        }
+        hashes
+    }
+
+    /// Returns the orientation of `b` that brings its hash closest to that of `a` within `max_distance`,
+    /// or `None` if no orientation does.
+    ///
+    /// The orientation is the transformation applied to `b` to match `a`, so `b` rotated 90 degrees
+    /// clockwise from `a` matches with `Orientation::Rotate270`. All 8 orientations of
+    /// `Orientation::ALL` are hashed, and ties resolve to the earlier one, so an unrotated match is
+    /// reported as `Orientation::Normal`.
+    fn matches_with_orientation(
+        &self,
+        a: &image::DynamicImage,
+        b: &image::DynamicImage,
+        max_distance: Distance,
+    ) -> Option<Orientation> {
+        let hash = self.hash(a);
+        Orientation::ALL
+            .iter()
+            .filter_map(|&orientation| {
+                let d = hash.distance(&self.hash(&orientation.apply(b))).ok()?;
+                Some((d, orientation))
+            })
+            .filter(|(d, _)| *d <= max_distance)
+            .min_by_key(|(d, _)| *d)
+            .map(|(_, orientation)| orientation)
+    }
+}
+
+/// Represents one of the 8 orientations of an image by rotations and mirroring.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    /// The image as is.
+    Normal,
+    /// The image rotated 90 degrees clockwise.
+    Rotate90,
+    /// The image rotated 180 degrees.
+    Rotate180,
+    /// The image rotated 270 degrees clockwise.
+    Rotate270,
+    /// The image flipped horizontally.
+    Mirror,
+    /// The image flipped horizontally and then rotated 90 degrees clockwise.
+    MirrorRotate90,
+    /// The image flipped horizontally and then rotated 180 degrees.
+    MirrorRotate180,
+    /// The image flipped horizontally and then rotated 270 degrees clockwise.
+    MirrorRotate270,
+}
+
+impl Orientation {
+    /// All orientations, in the order of the declaration.
+    pub const ALL: [Orientation; 8] = [
+        Orientation::Normal,
+        Orientation::Rotate90,
+        Orientation::Rotate180,
+        Orientation::Rotate270,
+        Orientation::Mirror,
+        Orientation::MirrorRotate90,
+        Orientation::MirrorRotate180,
+        Orientation::MirrorRotate270,
+    ];
+
+    /// Returns the orientation that brings an image stored with the EXIF orientation tag upright.
+    fn from_exif(orientation: u16) -> Option<Orientation> {
+        match orientation {
+            1 => Some(Orientation::Normal),
+            2 => Some(Orientation::Mirror),
+            3 => Some(Orientation::Rotate180),
+            4 => Some(Orientation::MirrorRotate180),
+            5 => Some(Orientation::MirrorRotate270),
+            6 => Some(Orientation::Rotate90),
+            7 => Some(Orientation::MirrorRotate90),
+            8 => Some(Orientation::Rotate270),
+            _ => None,
+        }
+    }
+
+    /// Returns the image transformed to this orientation.
+    pub fn apply(&self, image: &image::DynamicImage) -> image::DynamicImage {
+        match self {
+            Orientation::Normal => image.clone(),
+            Orientation::Rotate90 => image.rotate90(),
+            Orientation::Rotate180 => image.rotate180(),
+            Orientation::Rotate270 => image.rotate270(),
+            Orientation::Mirror => image.fliph(),
+            Orientation::MirrorRotate90 => image.fliph().rotate90(),
+            Orientation::MirrorRotate180 => image.fliph().rotate180(),
+            Orientation::MirrorRotate270 => image.fliph().rotate270(),
+        }
+    }
+}
+
+/// Provides matching of images with a hasher and a maximum distance.
+pub struct Matcher {
+    hasher: Box<dyn ImageHasher>,
+    max_distance: Distance,
+}
+
+impl Matcher {
+    /// Creates a new `Matcher` with the hasher and the maximum distance for images to match.
+    pub fn new(hasher: Box<dyn ImageHasher>, max_distance: Distance) -> Self {
+        Matcher {
+            hasher,
+            max_distance,
+        }
+    }
+
+    /// Calculates the hash of the image with the hasher.
+    pub fn hash(&self, image: &image::DynamicImage) -> Hash {
+        self.hasher.hash(image)
+    }
+
+    /// Returns whether the hashes of the images are within the maximum distance.
+    pub fn matches(&self, a: &image::DynamicImage, b: &image::DynamicImage) -> bool {
+        matches!(self.hash(a).distance(&self.hash(b)), Ok(d) if d <= self.max_distance)
+    }
+}
+
+/// Provides hashing of video frames as they arrive, keeping the hashes of the recent frames.
+///
+/// The distance between consecutive frames detects scene changes without buffering the frames.
+pub struct FrameHasher {
+    hasher: Box<dyn ImageHasher>,
+    history: std::collections::VecDeque<Hash>,
+    history_len: usize,
+    last_distance: Option<Distance>,
+}
+
+impl FrameHasher {
+    /// Creates a new `FrameHasher` with the hasher, keeping the hashes of the last 2 frames.
+    pub fn new(hasher: Box<dyn ImageHasher>) -> Self {
+        FrameHasher {
+            hasher,
+            history: std::collections::VecDeque::new(),
+            history_len: 2,
+            last_distance: None,
+        }
+    }
+
+    /// Constructs a frame hasher keeping the hashes of the last `len` frames.
+    ///
+    /// At least the last 2 frames are kept to compare consecutive frames, so `len` below 2 acts as 2.
+    pub fn with_history(self, len: usize) -> Self {
+        FrameHasher {
+            history_len: len.max(2),
+            ..self
+        }
+    }
+
+    /// Calculates the hash of the frame and appends it to the recent hashes.
+    ///
+    /// The oldest hash is dropped once the history is full.
+    pub fn push(&mut self, frame: &image::DynamicImage) -> Hash {
+        let hash = self.hasher.hash(frame);
+        self.last_distance = self
+            .history
+            .back()
+            .and_then(|last| last.distance(&hash).ok());
+        if self.history.len() == self.history_len {
+            self.history.pop_front();
+        }
+        self.history.push_back(Hash {
+            bits: hash.bits.clone(),
+        });
+        hash
+    }
+
+    /// Returns the distance between the last two frames, or `None` if fewer than two frames were pushed.
+    pub fn last_distance(&self) -> Option<Distance> {
+        self.last_distance
+    }
+
+    /// Returns whether the last frame is farther than `max_distance` from the frame before it.
+    pub fn is_scene_change(&self, max_distance: Distance) -> bool {
+        matches!(self.last_distance, Some(d) if d > max_distance)
+    }
+
+    /// Returns an iterator over the hashes of the recent frames, from the oldest to the latest.
+    pub fn recent(&self) -> impl Iterator<Item = &Hash> + '_ {
+        self.history.iter()
+    }
+
+    /// Clears the recent hashes, such as at the start of another video.
+    pub fn reset(&mut self) {
+        self.history.clear();
+        self.last_distance = None;
+    }
+}
+
+/// Represents a grayscale image.
+///
+/// A preprocessed image can be hashed by several hashers with `hash_gray`.
+/// The pixel type defaults to `u8`; wider types such as `u16` keep the precision of 10-, 12- and 16-bit sources.
+pub struct GrayscaleImage<P = u8> {
+    pixels: Vec<P>,
+    width: usize,
+    height: usize,
+}
+
+impl<P: Into<f64> + Copy> GrayscaleImage<P> {
+    /// Creates a new `GrayscaleImage` from the flattened pixels.
+    fn new(pixels: Vec<P>, width: usize, height: usize) -> Self {
+        assert_eq!(pixels.len(), width * height);
+        GrayscaleImage {
+            pixels,
+            width,
+            height,
+        }
+    }
+
+    /// Creates a new `GrayscaleImage` from the row-major luma pixels.
+    ///
+    /// Returns `None` if the length of `pixels` is not `width * height`.
+    pub fn from_luma(pixels: Vec<P>, width: usize, height: usize) -> Option<Self> {
+        if pixels.len() != width * height {
+            return None;
+        }
+        Some(GrayscaleImage::new(pixels, width, height))
+    }
+
+    /// Returns an iterator over the pixels as `f64`.
+    fn iter_pixels(&self) -> impl Iterator<Item = f64> + '_ {
+        self.pixels.iter().map(|&v| v.into())
+    }
+
+    /// Returns an iterator over the rows as `f64`.
+    fn iter_rows(&self) -> impl Iterator<Item = impl Iterator<Item = f64> + '_> + '_ {
+        self.pixels
+            .chunks(self.width)
+            .map(|row| row.iter().map(|&v| v.into()))
+    }
+
+    /// Returns the variance of the pixels.
+    ///
+    /// `try_hash` rejects preprocessed images whose variance is less than `MIN_VARIANCE`.
+    pub fn variance(&self) -> f64 {
+        let n = self.pixels.len() as f64;
+        let mean = self.iter_pixels().sum::<f64>() / n;
+        self.iter_pixels()
+            .map(|v| (v - mean) * (v - mean))
+            .sum::<f64>()
+            / n
+    }
+
+    /// Rejects images whose size is not the expected `(width, height)`.
+    fn check_size(&self, expected: (usize, usize)) -> Result<(), HashError> {
+        if (self.width, self.height) == expected {
+            Ok(())
+        } else {
+            Err(HashError::UnexpectedSize(
+                expected,
+                (self.width, self.height),
+            ))
+        }
+    }
+
+    /// Rejects nearly constant images whose hashes would be degenerate.
+    fn check_contrast(&self) -> Result<(), HashError> {
+        if self.variance() < MIN_VARIANCE {
+            Err(HashError::LowContrast)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// The minimum pixel variance for an image to be considered hashable.
+pub const MIN_VARIANCE: f64 = 1.0;
+
+/// Returns the variance of the pixels of the image converted to 8-bit grayscale.
+///
+/// The variance of an empty image is NaN.
+pub fn grayscale_variance(image: &image::DynamicImage) -> f64 {
+    GrayscaleImage::from(image.to_luma8()).variance()
+}
+
+/// Returns whether the grayscale variance of the image is at least `min_variance`.
+///
+/// Blank, uniform or extremely low-detail images have meaningless hashes that collide with each
+/// other, so they can be skipped before hashing. Unlike `try_hash`, this checks the source image
+/// rather than the preprocessed one. `MIN_VARIANCE` is a lenient threshold, and empty images are
+/// never hashable.
+pub fn is_hashable(image: &image::DynamicImage, min_variance: f64) -> bool {
+    grayscale_variance(image) >= min_variance
+}
+
+impl GrayscaleImage {
+    /// Creates a new `GrayscaleImage` from the row-major CMYK pixels, 4 bytes per pixel with 255 for full ink.
+    ///
+    /// Each pixel is converted to RGB by `(255 - ink) * (255 - K) / 255` for the cyan, magenta and yellow
+    /// inks, and then to luma with the sRGB weights, without color management. CMYK JPEG files are
+    /// converted to RGB by the `image` decoder with the same formula, up to its rounding; see
+    /// `ColorSpace::Cmyk` for hashing them by this formula.
+    ///
+    /// Returns `None` if the length of `pixels` is not `width * height * 4`.
+    pub fn from_cmyk(pixels: &[u8], width: usize, height: usize) -> Option<Self> {
+        if pixels.len() != width * height * 4 {
+            return None;
+        }
+        let luma = pixels
+            .chunks_exact(4)
+            .map(|p| {
+                let ink =
+                    0.2126 * f64::from(p[0]) + 0.7152 * f64::from(p[1]) + 0.0722 * f64::from(p[2]);
+                ((255.0 - ink) * (255.0 - f64::from(p[3])) / 255.0).round() as u8
+            })
+            .collect();
+        Some(GrayscaleImage::new(luma, width, height))
+    }
+}
+
+impl GrayscaleImage<f32> {
+    /// Resamples the image to `(width, height)` by nearest neighbor if it has another size.
+    fn fit(self, (width, height): (usize, usize)) -> Self {
+        if (self.width, self.height) == (width, height) {
+            return self;
+        }
+        if self.pixels.is_empty() {
+            return GrayscaleImage::new(vec![0.0; width * height], width, height);
+        }
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let sy = (2 * y + 1) * self.height / (2 * height);
+            for x in 0..width {
+                let sx = (2 * x + 1) * self.width / (2 * width);
+                pixels.push(self.pixels[sy * self.width + sx]);
+            }
+        }
+        GrayscaleImage::new(pixels, width, height)
+    }
+}
+
+impl From<image::DynamicImage> for GrayscaleImage {
+    fn from(image: image::DynamicImage) -> Self {
+        image.into_luma8().into()
+    }
+}
+
+impl From<image::GrayImage> for GrayscaleImage {
+    fn from(image: image::GrayImage) -> Self {
+        let width = image.width() as usize;
+        let height = image.height() as usize;
+        GrayscaleImage::new(image.into_raw(), width, height)
+    }
+}
+
+impl From<image::ImageBuffer<image::Luma<u16>, Vec<u16>>> for GrayscaleImage<u16> {
+    fn from(image: image::ImageBuffer<image::Luma<u16>, Vec<u16>>) -> Self {
+        let width = image.width() as usize;
+        let height = image.height() as usize;
+        GrayscaleImage::new(image.into_raw(), width, height)
+    }
+}
+
+/// Converts the RGBA8 pixels to grayscale with the sRGB luma weights, ignoring alpha.
+fn rgba_to_gray(pixels: &[u8], width: usize, height: usize) -> image::GrayImage {
+    assert_eq!(pixels.len(), width * height * 4);
+    let luma = pixels
+        .chunks_exact(4)
+        .map(|p| {
+            ((2126 * u32::from(p[0]) + 7152 * u32::from(p[1]) + 722 * u32::from(p[2])) / 10000)
+                as u8
+        })
+        .collect();
+    image::GrayImage::from_raw(width as u32, height as u32, luma).unwrap()
+}
+
+fn thumbnail(image: &image::GrayImage, width: usize, height: usize) -> image::GrayImage {
+    image::imageops::thumbnail(image, width as u32, height as u32)
+}
+
+/// Converts the image to grayscale with the sRGB luma weights of `rgba_to_gray`, independent of `image`.
+fn stable_grayscale(image: &image::DynamicImage) -> image::DynamicImage {
+    if let image::DynamicImage::ImageLuma8(image) = image {
+        return image::DynamicImage::ImageLuma8(image.clone());
+    }
+    let rgba = image.to_rgba8();
+    let (width, height) = (rgba.width() as usize, rgba.height() as usize);
+    image::DynamicImage::ImageLuma8(rgba_to_gray(rgba.as_raw(), width, height))
+}
+
+/// Resizes the image with a separable Lanczos3 filter implemented in this crate, independent of `image`.
+///
+/// The filter support is widened by the scale factor when downscaling, and each output pixel is
+/// rounded and clamped to 8 bits once after both passes.
+fn stable_resize(image: &image::GrayImage, width: usize, height: usize) -> image::GrayImage {
+    let (src_width, src_height) = (image.width() as usize, image.height() as usize);
+    if src_width == 0 || src_height == 0 {
+        return image::GrayImage::new(width as u32, height as u32);
+    }
+    let columns = lanczos3_taps(src_width, width);
+    let rows = lanczos3_taps(src_height, height);
+    let pixels = image.as_raw();
+    let mut horizontal = vec![0.0; width * src_height];
+    for y in 0..src_height {
+        let row = &pixels[y * src_width..(y + 1) * src_width];
+        for (x, (start, weights)) in columns.iter().enumerate() {
+            horizontal[y * width + x] = weights
+                .iter()
+                .enumerate()
+                .map(|(i, w)| w * f64::from(row[start + i]))
+                .sum();
+        }
+    }
+    let mut result = Vec::with_capacity(width * height);
+    for (start, weights) in &rows {
+        for x in 0..width {
+            let v: f64 = weights
+                .iter()
+                .enumerate()
+                .map(|(i, w)| w * horizontal[(start + i) * width + x])
+                .sum();
+            result.push(v.round().clamp(0.0, 255.0) as u8);
+        }
+    }
+    image::GrayImage::from_raw(width as u32, height as u32, result).unwrap()
+}
+
+/// Returns the first source index and the normalized Lanczos3 weights of each destination index.
+fn lanczos3_taps(src_len: usize, dst_len: usize) -> Vec<(usize, Vec<f64>)> {
+    let ratio = src_len as f64 / dst_len as f64;
+    let scale = ratio.max(1.0);
+    let support = 3.0 * scale;
+    (0..dst_len)
+        .map(|i| {
+            let center = (i as f64 + 0.5) * ratio;
+            let start = ((center - support).floor().max(0.0) as usize).min(src_len - 1);
+            let end = ((center + support).ceil() as usize).clamp(start + 1, src_len);
+            let mut weights: Vec<f64> = (start..end)
+                .map(|j| lanczos3((j as f64 + 0.5 - center) / scale))
+                .collect();
+            let total: f64 = weights.iter().sum();
+            if total != 0.0 {
+                weights.iter_mut().for_each(|w| *w /= total);
+            }
+            (start, weights)
+        })
+        .collect()
+}
+
+fn lanczos3(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else if x.abs() < 3.0 {
+        let px = std::f64::consts::PI * x;
+        3.0 * px.sin() * (px / 3.0).sin() / (px * px)
+    } else {
+        0.0
+    }
+}
+
+fn resize(image: &image::DynamicImage, width: usize, height: usize) -> image::DynamicImage {
+    image.resize_exact(
+        width as u32,
+        height as u32,
+        image::imageops::FilterType::Lanczos3,
+    )
+}
+
+/// Represents how images whose aspect ratio differs from the image size are handled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AspectPolicy {
+    /// Stretches the whole image to the image size. This is the default.
+    #[default]
+    Stretch,
+    /// Crops the largest centered square before resizing.
+    CropSquare,
+}
+
+impl AspectPolicy {
+    fn apply(&self, image: image::DynamicImage) -> image::DynamicImage {
+        match self {
+            AspectPolicy::Stretch => image,
+            AspectPolicy::CropSquare => {
+                let (x, y, side) = center_square(image.width(), image.height());
+                image.crop_imm(x, y, side, side)
+            }
+        }
+    }
+}
+
+/// Represents a function resizing the grayscale image to the width and height.
+pub type GrayResizer = fn(&image::GrayImage, usize, usize) -> image::GrayImage;
+
+/// The grayscale conversion and gray resizer of a hasher, which `with_stable_pipeline` replaces.
+type PipelineSteps = (
+    fn(&image::DynamicImage) -> image::DynamicImage,
+    Option<GrayResizer>,
+);
+
+/// Returns the pipeline steps and the steps they replaced after `with_stable_pipeline`.
+///
+/// Enabling saves the current steps unless already enabled, and disabling restores the saved ones,
+/// so steps customized before enabling come back.
+fn stable_pipeline_steps(
+    stable_pipeline: bool,
+    current: PipelineSteps,
+    replaced: Option<PipelineSteps>,
+) -> (PipelineSteps, Option<PipelineSteps>) {
+    if stable_pipeline {
+        (
+            (stable_grayscale, Some(stable_resize as GrayResizer)),
+            replaced.or(Some(current)),
+        )
+    } else {
+        (replaced.unwrap_or(current), None)
+    }
+}
+
+/// Represents a function returning the left, top, width and height of the region of interest in an image.
+pub type RegionDetector = fn(&image::DynamicImage) -> Option<(u32, u32, u32, u32)>;
+
+/// Represents the color space of the pixels of source images.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// RGB or grayscale pixels, converted to grayscale by the grayscale conversion function.
+    #[default]
+    Rgb,
+    /// CMYK pixels, converted to grayscale by the formula of `GrayscaleImage::from_cmyk`.
+    ///
+    /// With the `jpeg` feature, `hash_path` decodes CMYK JPEG files to their ink values and converts
+    /// them by the formula. Images already decoded to RGB, such as CMYK JPEG files opened by the `image`
+    /// crate, hold `(255 - ink) * (255 - K) / 255` in each channel, so their luma is taken with the same
+    /// sRGB weights, up to the rounding of the decoder.
+    Cmyk,
+}
+
+/// Represents the preprocessing steps applied to images around resizing.
+///
+/// The steps are applied in a fixed order regardless of the order of the builder calls:
+///
+/// 1. crop
+/// 2. region detection
+/// 3. grayscale conversion, by the color space or `with_python_compat`
+/// 4. aspect policy
+/// 5. gamma correction
+/// 6. blur
+/// 7. edge detection
+/// 8. histogram equalization
+/// 9. standardization
+/// 10. posterization
+/// 11. resizing by the hasher
+/// 12. linearization
+#[derive(Clone, Copy)]
+pub struct Preprocess {
+    crop: Option<(u32, u32, u32, u32)>,
+    region_detector: Option<RegionDetector>,
+    grayscale: fn(&image::DynamicImage) -> image::DynamicImage,
+    grayscale_disabled: bool,
+    color_space: ColorSpace,
+    python_compat: bool,
+    aspect_policy: AspectPolicy,
+    gamma: Option<f64>,
+    blur: Option<f32>,
+    edges: bool,
+    histogram_equalization: bool,
+    standardize: bool,
+    posterize: Option<u8>,
+    linearize: bool,
+}
+
+impl Preprocess {
+    /// Creates a new `Preprocess` with default parameters.
+    pub fn new() -> Self {
+        Preprocess::default()
+    }
+
+    /// Constructs a preprocess cropping the region at (`x`, `y`) of `width` x `height` first.
+    pub fn with_crop(self, x: u32, y: u32, width: u32, height: u32) -> Self {
+        Preprocess {
+            crop: Some((x, y, width, height)),
+            ..self
+        }
+    }
+
+    /// Constructs a preprocess cropping the image to the region found by the detector.
+    ///
+    /// The detector returns the left, top, width and height of the region, such as the bounding box
+    /// of a face, or `None` to keep the whole image. It runs after the fixed crop of `with_crop`.
+    pub fn with_region_detector(self, region_detector: RegionDetector) -> Self {
+        Preprocess {
+            region_detector: Some(region_detector),
+            ..self
+        }
+    }
+
+    /// Constructs a preprocess with the grayscale conversion function.
+    pub fn with_grayscale(
+        self,
+        grayscale: fn(&image::DynamicImage) -> image::DynamicImage,
+    ) -> Self {
+        Preprocess { grayscale, ..self }
+    }
+
+    /// Constructs a preprocess skipping the grayscale conversion for images already in grayscale.
+    ///
+    /// A non-luma image is then not reduced by the grayscale conversion function but by the `image`
+    /// crate's luma conversion wherever a later step or the resizer needs 8-bit luma, which may happen
+    /// after resizing.
+    pub fn with_grayscale_disabled(self, grayscale_disabled: bool) -> Self {
+        Preprocess {
+            grayscale_disabled,
+            ..self
+        }
+    }
+
+    /// Constructs a preprocess converting images to grayscale by the color space of their pixels.
+    ///
+    /// `ColorSpace::Cmyk` takes the place of the grayscale conversion function.
+    pub fn with_color_space(self, color_space: ColorSpace) -> Self {
+        Preprocess {
+            color_space,
+            ..self
+        }
+    }
+
+    /// Constructs a preprocess matching the Python imagehash library.
+    ///
+    /// Images are converted to grayscale like Pillow's `convert("L")`, by
+    /// `(19595 * R + 38470 * G + 7471 * B + 32768) >> 16`, in place of the grayscale conversion
+    /// function, and `format_hash` on the hashers formats hashes like `Hash::to_python_hex`. The bits
+    /// are already in the row-major order of the library. Hashes agree for images decoded and resized
+    /// alike, such as 8-bit images at the sizes the algorithms resize to.
+    pub fn with_python_compat(self, python_compat: bool) -> Self {
+        Preprocess {
+            python_compat,
+            ..self
+        }
+    }
+
+    /// Constructs a preprocess with the policy for non-square images.
+    pub fn with_aspect_policy(self, aspect_policy: AspectPolicy) -> Self {
+        Preprocess {
+            aspect_policy,
+            ..self
+        }
+    }
+
+    /// Constructs a preprocess raising each normalized grayscale value to the power of `gamma`.
+    pub fn with_gamma(self, gamma: f64) -> Self {
+        Preprocess {
+            gamma: Some(gamma),
+            ..self
+        }
+    }
+
+    /// Constructs a preprocess applying a Gaussian blur with the standard deviation `sigma`.
+    pub fn with_blur(self, sigma: f32) -> Self {
+        Preprocess {
+            blur: Some(sigma),
+            ..self
+        }
+    }
+
+    /// Constructs a preprocess replacing the grayscale image with its 3x3 Sobel gradient magnitude.
+    ///
+    /// Hashes of the edges key on the structure of images and are robust to recoloring and texture changes.
+    pub fn with_edges(self, edges: bool) -> Self {
+        Preprocess { edges, ..self }
+    }
+
+    /// Constructs a preprocess with histogram equalization of the grayscale image.
+    pub fn with_histogram_equalization(self, histogram_equalization: bool) -> Self {
+        Preprocess {
+            histogram_equalization,
+            ..self
+        }
+    }
+
+    /// Constructs a preprocess standardizing the grayscale image to a fixed mean and standard deviation.
+    pub fn with_standardize(self, standardize: bool) -> Self {
+        Preprocess {
+            standardize,
+            ..self
+        }
+    }
+
+    /// Constructs a preprocess quantizing the grayscale image to `levels` evenly spaced tones.
+    ///
+    /// Each pixel is mapped to its nearest tone, so mild tonal shifts from recoloring or compression
+    /// leave most pixels unchanged. Levels below 2 are treated as 2.
+    pub fn with_posterize(self, levels: u8) -> Self {
+        Preprocess {
+            posterize: Some(levels),
+            ..self
+        }
+    }
+
+    /// Constructs a preprocess converting the resized sRGB values to linear light.
+    ///
+    /// The converted values are scaled to the range from 0.0 to 255.0, so the mean and the DCT are
+    /// computed in linear light.
+    pub fn with_linearize(self, linearize: bool) -> Self {
+        Preprocess { linearize, ..self }
+    }
+
+    /// Applies the crop and then the region detector, which is run once on the cropped image.
+    fn cropped<'a>(
+        &self,
+        image: &'a image::DynamicImage,
+    ) -> std::borrow::Cow<'a, image::DynamicImage> {
+        let mut image = std::borrow::Cow::Borrowed(image);
+        if let Some((x, y, width, height)) = self.crop {
+            image = std::borrow::Cow::Owned(image.crop_imm(x, y, width, height));
+        }
+        if let Some((x, y, width, height)) = self.region_detector.and_then(|detect| detect(&image))
+        {
+            image = std::borrow::Cow::Owned(image.crop_imm(x, y, width, height));
+        }
+        image
+    }
+
+    fn apply(&self, image: &image::DynamicImage) -> image::DynamicImage {
+        self.apply_cropped(&self.cropped(image))
+    }
+
+    /// Applies the steps after the crop and the region detector to the image returned by `cropped`.
+    fn apply_cropped(&self, image: &image::DynamicImage) -> image::DynamicImage {
+        let mut image = if self.grayscale_disabled {
+            image.clone()
+        } else if self.color_space == ColorSpace::Cmyk {
+            cmyk_luma(image)
+        } else if self.python_compat {
+            python_luma(image)
+        } else {
+            (self.grayscale)(image)
+        };
+        image = self.aspect_policy.apply(image);
+        if let Some(gamma) = self.gamma {
+            image = image::DynamicImage::ImageLuma8(adjust_gamma(image.into_luma8(), gamma));
+        }
+        if let Some(sigma) = self.blur {
+            image = image.blur(sigma);
+        }
+        if self.edges {
+            image = image::DynamicImage::ImageLuma8(sobel(&image.into_luma8()));
+        }
+        if self.histogram_equalization {
+            image = image::DynamicImage::ImageLuma8(equalize_histogram(image.into_luma8()));
+        }
+        if self.standardize {
+            image = image::DynamicImage::ImageLuma8(standardize(image.into_luma8()));
+        }
+        if let Some(levels) = self.posterize {
+            image = image::DynamicImage::ImageLuma8(posterize(image.into_luma8(), levels));
+        }
+        image
+    }
+}
+
+impl Preprocess {
+    /// Opens the image file for `hash_path` within `default_limits`, reduced to about `size` by
+    /// `open_jpeg_scaled` unless `full` is set or a crop or a region detector needs the full image.
+    ///
+    /// With `ColorSpace::Cmyk` and the `jpeg` feature, CMYK JPEG files are decoded to their luma by
+    /// `GrayscaleImage::from_cmyk`.
+    fn open<P: AsRef<std::path::Path>>(
+        &self,
+        path: P,
+        size: (usize, usize),
+        full: bool,
+    ) -> Result<image::DynamicImage, HashError> {
+        let full = full || self.crop.is_some() || self.region_detector.is_some();
+        #[cfg(feature = "jpeg")]
+        if self.color_space == ColorSpace::Cmyk {
+            let scale = if full { None } else { Some(size) };
+            if let Some(image) = open_cmyk_jpeg(&path, scale, default_limits())? {
+                return Ok(image);
+            }
+        }
+        if full {
+            open_image(path, default_limits())
+        } else {
+            open_jpeg_scaled(path, size.0, size.1, default_limits())
+        }
+    }
+
+    /// Returns the alpha channel of the image returned by `cropped` after the aspect policy.
+    ///
+    /// The alpha channel is then aligned with the image returned by `apply_cropped`.
+    fn alpha(&self, image: &image::DynamicImage) -> image::GrayImage {
+        let rgba = image.to_rgba8();
+        let alpha = image::GrayImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+            image::Luma([rgba.get_pixel(x, y)[3]])
+        });
+        self.aspect_policy
+            .apply(image::DynamicImage::ImageLuma8(alpha))
+            .into_luma8()
+    }
+
+    /// Applies the steps after resizing.
+    fn finish(&self, image: GrayscaleImage) -> GrayscaleImage<f32> {
+        let pixels = if self.linearize {
+            let table = srgb_to_linear_table();
+            image.pixels.iter().map(|&v| table[v as usize]).collect()
+        } else {
+            image.pixels.iter().map(|&v| f32::from(v)).collect()
+        };
+        GrayscaleImage::new(pixels, image.width, image.height)
+    }
+}
+
+/// Returns the linear light of each sRGB-encoded value, scaled to the range from 0.0 to 255.0.
+fn srgb_to_linear_table() -> [f32; 256] {
+    let mut table = [0.0; 256];
+    for (i, v) in table.iter_mut().enumerate() {
+        let c = i as f64 / 255.0;
+        let linear = if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        };
+        *v = (linear * 255.0) as f32;
+    }
+    table
+}
+
+/// Converts the image to luma like Pillow's `convert("L")`.
+fn python_luma(image: &image::DynamicImage) -> image::DynamicImage {
+    if let image::DynamicImage::ImageLuma8(_) = image {
+        return image.clone();
+    }
+    let rgb = image.to_rgb8();
+    image::DynamicImage::ImageLuma8(image::GrayImage::from_fn(
+        rgb.width(),
+        rgb.height(),
+        |x, y| {
+            let p = rgb.get_pixel(x, y);
+            let luma = 19595 * u32::from(p[0]) + 38470 * u32::from(p[1]) + 7471 * u32::from(p[2]);
+            image::Luma([((luma + 0x8000) >> 16) as u8])
+        },
+    ))
+}
+
+/// Converts the RGB pixels decoded from CMYK to luma with the sRGB weights of `GrayscaleImage::from_cmyk`.
+fn cmyk_luma(image: &image::DynamicImage) -> image::DynamicImage {
+    if let image::DynamicImage::ImageLuma8(_) = image {
+        return image.clone();
+    }
+    let rgb = image.to_rgb8();
+    image::DynamicImage::ImageLuma8(image::GrayImage::from_fn(
+        rgb.width(),
+        rgb.height(),
+        |x, y| {
+            let p = rgb.get_pixel(x, y);
+            let luma =
+                0.2126 * f64::from(p[0]) + 0.7152 * f64::from(p[1]) + 0.0722 * f64::from(p[2]);
+            image::Luma([luma.round() as u8])
+        },
+    ))
+}
+
+/// Decodes the CMYK JPEG file within the limits to its luma by `GrayscaleImage::from_cmyk`, reduced to
+/// about `width` x `height` if `scale` is given.
+///
+/// Returns `None` if the file is not a CMYK JPEG.
+#[cfg(feature = "jpeg")]
+fn open_cmyk_jpeg<P: AsRef<std::path::Path>>(
+    path: P,
+    scale: Option<(usize, usize)>,
+    limits: image::io::Limits,
+) -> Result<Option<image::DynamicImage>, HashError> {
+    if image::ImageFormat::from_path(&path).ok() != Some(image::ImageFormat::Jpeg) {
+        return Ok(None);
+    }
+    let decoding = |e: jpeg_decoder::Error| {
+        HashError::Decode(image::ImageError::Decoding(
+            image::error::DecodingError::new(image::ImageFormat::Jpeg.into(), e),
+        ))
+    };
+    let file = std::fs::File::open(path).map_err(image::ImageError::IoError)?;
+    let mut decoder = jpeg_decoder::Decoder::new(std::io::BufReader::new(file));
+    decoder.read_info().map_err(decoding)?;
+    let info = decoder.info().unwrap();
+    if info.pixel_format != jpeg_decoder::PixelFormat::CMYK32 {
+        return Ok(None);
+    }
+    let (width, height) = match scale {
+        Some((width, height)) => {
+            let clamp = |v: usize| v.min(usize::from(u16::MAX)) as u16;
+            decoder
+                .scale(clamp(width), clamp(height))
+                .map_err(decoding)?
+        }
+        None => (info.width, info.height),
+    };
+    limits.check_dimensions(u32::from(width), u32::from(height))?;
+    if let Some(max_alloc) = limits.max_alloc {
+        decoder.set_max_decoding_buffer_size(usize::try_from(max_alloc).unwrap_or(usize::MAX));
+    }
+    let pixels = decoder.decode().map_err(decoding)?;
+    let (width, height) = (usize::from(width), usize::from(height));
+    let gray = GrayscaleImage::from_cmyk(&pixels, width, height)
+        .ok_or_else(|| decoding(jpeg_decoder::Error::Format("truncated CMYK pixels".into())))?;
+    let luma = image::GrayImage::from_raw(width as u32, height as u32, gray.pixels).unwrap();
+    Ok(Some(image::DynamicImage::ImageLuma8(luma)))
+}
+
+impl std::fmt::Debug for Preprocess {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Preprocess")
+            .field("crop", &self.crop)
+            .field("region_detector", &self.region_detector.is_some())
+            .field("grayscale_disabled", &self.grayscale_disabled)
+            .field("color_space", &self.color_space)
+            .field("python_compat", &self.python_compat)
+            .field("aspect_policy", &self.aspect_policy)
+            .field("gamma", &self.gamma)
+            .field("blur", &self.blur)
+            .field("edges", &self.edges)
+            .field("histogram_equalization", &self.histogram_equalization)
+            .field("standardize", &self.standardize)
+            .field("posterize", &self.posterize)
+            .field("linearize", &self.linearize)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for Preprocess {
+    /// Creates a new `Preprocess` with default parameters.
+    fn default() -> Self {
+        Preprocess {
+            crop: None,
+            region_detector: None,
+            grayscale: image::DynamicImage::grayscale,
+            grayscale_disabled: false,
+            color_space: ColorSpace::default(),
+            python_compat: false,
+            aspect_policy: AspectPolicy::default(),
+            gamma: None,
+            blur: None,
+            edges: false,
+            histogram_equalization: false,
+            standardize: false,
+            posterize: None,
+            linearize: false,
+        }
+    }
+}
+
+/// Applies the gamma correction to the grayscale image.
+fn adjust_gamma(mut image: image::GrayImage, gamma: f64) -> image::GrayImage {
+    let mut table = [0u8; 256];
+    for (i, v) in table.iter_mut().enumerate() {
+        *v = (255.0 * (i as f64 / 255.0).powf(gamma)).round() as u8;
+    }
+    for pixel in image.pixels_mut() {
+        pixel[0] = table[pixel[0] as usize];
+    }
+    image
+}
+
+/// Quantizes the grayscale image to the nearest of `levels` evenly spaced tones from 0 to 255.
+fn posterize(mut image: image::GrayImage, levels: u8) -> image::GrayImage {
+    let steps = f64::from(levels.max(2) - 1);
+    let mut table = [0u8; 256];
+    for (i, v) in table.iter_mut().enumerate() {
+        let level = (i as f64 * steps / 255.0).round();
+        *v = (level * 255.0 / steps).round() as u8;
+    }
+    for pixel in image.pixels_mut() {
+        pixel[0] = table[pixel[0] as usize];
+    }
+    image
+}
+
+/// Returns the Sobel gradient magnitude of the grayscale image, replicating the edge pixels.
+fn sobel(image: &image::GrayImage) -> image::GrayImage {
+    let (width, height) = image.dimensions();
+    let at = |x: i64, y: i64| {
+        let x = x.clamp(0, i64::from(width) - 1) as u32;
+        let y = y.clamp(0, i64::from(height) - 1) as u32;
+        i64::from(image.get_pixel(x, y)[0])
+    };
+    image::GrayImage::from_fn(width, height, |x, y| {
+        let (x, y) = (i64::from(x), i64::from(y));
+        let gx = at(x + 1, y - 1) + 2 * at(x + 1, y) + at(x + 1, y + 1)
+            - at(x - 1, y - 1)
+            - 2 * at(x - 1, y)
+            - at(x - 1, y + 1);
+        let gy = at(x - 1, y + 1) + 2 * at(x, y + 1) + at(x + 1, y + 1)
+            - at(x - 1, y - 1)
+            - 2 * at(x, y - 1)
+            - at(x + 1, y - 1);
+        let magnitude = ((gx * gx + gy * gy) as f64).sqrt();
+        image::Luma([magnitude.round().min(255.0) as u8])
+    })
+}
+
+/// Equalizes the histogram of the grayscale image using the cumulative distribution of 256 bins.
+fn equalize_histogram(mut image: image::GrayImage) -> image::GrayImage {
+    let mut cdf = [0usize; 256];
+    for pixel in image.pixels() {
+        cdf[pixel[0] as usize] += 1;
+    }
+    for i in 1..256 {
+        cdf[i] += cdf[i - 1];
+    }
+    let total = cdf[255];
+    let min = cdf.iter().copied().find(|&c| c > 0).unwrap_or(0);
+    if total == min {
+        return image;
+    }
+    for pixel in image.pixels_mut() {
+        let c = cdf[pixel[0] as usize];
+        pixel[0] = ((c - min) as f64 * 255.0 / (total - min) as f64).round() as u8;
+    }
+    image
+}
+
+/// Standardizes the grayscale image to the mean `STANDARD_MEAN` and the standard deviation `STANDARD_DEVIATION`.
+fn standardize(mut image: image::GrayImage) -> image::GrayImage {
+    let n = image.pixels().len() as f64;
+    let mean = image.pixels().map(|p| f64::from(p[0])).sum::<f64>() / n;
+    let variance = image
+        .pixels()
+        .map(|p| (f64::from(p[0]) - mean) * (f64::from(p[0]) - mean))
+        .sum::<f64>()
+        / n;
+    let deviation = variance.sqrt();
+    for pixel in image.pixels_mut() {
+        let z = if deviation > 0.0 {
+            (f64::from(pixel[0]) - mean) / deviation
+        } else {
+            0.0
+        };
+        pixel[0] = (STANDARD_MEAN + STANDARD_DEVIATION * z)
+            .round()
+            .clamp(0.0, 255.0) as u8;
+    }
+    image
+}
+
+/// The mean of standardized images.
+const STANDARD_MEAN: f64 = 128.0;
+
+/// The standard deviation of standardized images.
+const STANDARD_DEVIATION: f64 = 32.0;
+
+/// Checks that both sides of the source image are at least `min_source`.
+fn check_source(image: &image::DynamicImage, min_source: usize) -> Result<(), HashError> {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    if width < min_source || height < min_source {
+        return Err(HashError::SourceTooSmall(width, height));
+    }
+    Ok(())
+}
+
+/// Returns the left, top and side of the largest centered square.
+fn center_square(width: u32, height: u32) -> (u32, u32, u32) {
+    let side = width.min(height);
+    ((width - side) / 2, (height - side) / 2, side)
+}
+
+/// Implements the methods of a hasher forwarding to its `Preprocess`.
+macro_rules! preprocess_builders {
+    () => {
+        /// Constructs a hasher with the preprocessing steps applied before resizing.
+        ///
+        /// This replaces any preprocessing configured by the other builder methods.
+        pub fn with_preprocess(self, preprocess: Preprocess) -> Self {
+            Self { preprocess, ..self }
+        }
+
+        /// Constructs a hasher cropping images to the region found by the detector before preprocessing.
+        ///
+        /// See `Preprocess::with_region_detector`. Images for which the detector returns `None` are hashed whole.
+        pub fn with_region_detector(self, region_detector: RegionDetector) -> Self {
+            Self {
+                preprocess: self.preprocess.with_region_detector(region_detector),
+                ..self
+            }
+        }
+
+        /// Constructs a hasher converting images to grayscale by the color space of their pixels.
+        ///
+        /// See `Preprocess::with_color_space`.
+        pub fn with_color_space(self, color_space: ColorSpace) -> Self {
+            Self {
+                preprocess: self.preprocess.with_color_space(color_space),
+                ..self
+            }
+        }
+
+        /// Constructs a hasher matching the grayscale conversion and hex format of the Python imagehash library.
+        ///
+        /// See `Preprocess::with_python_compat`.
+        pub fn with_python_compat(self, python_compat: bool) -> Self {
+            Self {
+                preprocess: self.preprocess.with_python_compat(python_compat),
+                ..self
+            }
+        }
+
+        /// Returns the hex string of the hash, as `Hash::to_python_hex` with `with_python_compat` or
+        /// as `Display` otherwise.
+        pub fn format_hash(&self, hash: &Hash) -> String {
+            if self.preprocess.python_compat {
+                hash.to_python_hex()
+            } else {
+                hash.to_string()
+            }
+        }
+
+        /// Constructs a hasher with the policy for non-square images.
+        ///
+        /// See `Preprocess::with_aspect_policy`.
+        pub fn with_aspect_policy(self, aspect_policy: AspectPolicy) -> Self {
+            Self {
+                preprocess: self.preprocess.with_aspect_policy(aspect_policy),
+                ..self
+            }
+        }
+
+        /// Constructs a hasher hashing the Sobel edges of the grayscale image instead of the image itself.
+        ///
+        /// See `Preprocess::with_edges`.
+        pub fn with_edges(self, edges: bool) -> Self {
+            Self {
+                preprocess: self.preprocess.with_edges(edges),
+                ..self
+            }
+        }
+
+        /// Constructs a hasher skipping the grayscale conversion for images already in grayscale.
+        ///
+        /// See `Preprocess::with_grayscale_disabled` for non-luma images.
+        pub fn with_grayscale_disabled(self, grayscale_disabled: bool) -> Self {
+            Self {
+                preprocess: self.preprocess.with_grayscale_disabled(grayscale_disabled),
+                ..self
+            }
+        }
+
+        /// Constructs a hasher quantizing the grayscale image to `levels` tones before resizing.
+        ///
+        /// See `Preprocess::with_posterize`.
+        pub fn with_posterize(self, levels: u8) -> Self {
+            Self {
+                preprocess: self.preprocess.with_posterize(levels),
+                ..self
+            }
+        }
+
+        /// Constructs a hasher with histogram equalization of the grayscale image before resizing.
+        ///
+        /// See `Preprocess::with_histogram_equalization`.
+        pub fn with_histogram_equalization(self, histogram_equalization: bool) -> Self {
+            Self {
+                preprocess: self
+                    .preprocess
+                    .with_histogram_equalization(histogram_equalization),
+                ..self
+            }
+        }
+
+        /// Constructs a hasher converting the resized sRGB values to linear light before thresholding.
+        ///
+        /// See `Preprocess::with_linearize`.
+        pub fn with_linearize(self, linearize: bool) -> Self {
+            Self {
+                preprocess: self.preprocess.with_linearize(linearize),
+                ..self
+            }
+        }
+
+        /// Constructs a hasher standardizing the grayscale image to a fixed mean and standard deviation before resizing.
+        ///
+        /// See `Preprocess::with_standardize`.
+        pub fn with_standardize(self, standardize: bool) -> Self {
+            Self {
+                preprocess: self.preprocess.with_standardize(standardize),
+                ..self
+            }
+        }
+    };
+}
+
+/// Provides average hash (aHash) calculation.
+pub struct AverageHash {
+    image_size: (usize, usize),
+    hash_size: (usize, usize),
+    resizer: fn(&image::DynamicImage, usize, usize) -> image::DynamicImage,
+    gray_resizer: Option<GrayResizer>,
+    replaced_steps: Option<PipelineSteps>,
+    preprocess: Preprocess,
+    adaptive: Option<usize>,
+    pivot: Pivot,
+    min_source: usize,
+    exif_orientation: bool,
+    native_blocks: bool,
+    center_weight: f64,
+}
+
+impl AverageHash {
+    /// Creates a new `AverageHasher` with default parameters.
+    pub fn new() -> Self {
+        AverageHash::default()
+    }
+
+    /// Constructs a hasher with the image size.
+    pub fn with_image_size(self, width: usize, height: usize) -> Self {
+        AverageHash {
+            image_size: (width, height),
+            ..self
+        }
+    }
+
+    /// Constructs a hasher with the hash size.
+    ///
+    /// The hash thresholds the top-left `width` x `height` block of the resized image, so an image
+    /// size larger than the hash size leaves the rest of the image out of the hash.
+    pub fn with_hash_size(self, width: usize, height: usize) -> Self {
+        AverageHash {
+            hash_size: (width, height),
+            ..self
+        }
+    }
+
+    /// Constructs a hasher with the square image size of `n` x `n`.
+    pub fn with_square_image_size(self, n: usize) -> Self {
+        self.with_image_size(n, n)
+    }
+
+    /// Constructs a hasher with the square hash size of `n` x `n`.
+    pub fn with_square_hash_size(self, n: usize) -> Self {
+        self.with_hash_size(n, n)
+    }
+
+    /// Constructs a hasher with the resizer function.
+    ///
+    /// The resizer receives the preprocessed grayscale image. Setting it overrides `with_gray_resizer`.
+    pub fn with_resizer(
+        self,
+        resizer: fn(&image::DynamicImage, usize, usize) -> image::DynamicImage,
+    ) -> Self {
+        AverageHash {
+            resizer,
+            gray_resizer: None,
+            ..self
+        }
+    }
+
+    /// Constructs a hasher with the resizer function operating on the preprocessed grayscale image.
+    ///
+    /// This avoids converting between `DynamicImage` and `GrayImage` around resizing.
+    /// Setting it overrides `with_resizer`.
+    pub fn with_gray_resizer(self, gray_resizer: GrayResizer) -> Self {
+        AverageHash {
+            gray_resizer: Some(gray_resizer),
+            ..self
+        }
+    }
+
+    /// Constructs a hasher downscaling with `image::imageops::thumbnail` instead of the resizer function.
+    ///
+    /// This is much faster than the default Lanczos3 filter for large source images, at the cost of
+    /// different hashes. It sets the gray resizer of `with_gray_resizer` when enabled and clears it otherwise.
+    pub fn with_thumbnail_resize(self, thumbnail_resize: bool) -> Self {
+        if thumbnail_resize {
+            self.with_gray_resizer(thumbnail)
+        } else {
+            AverageHash {
+                gray_resizer: None,
+                ..self
+            }
+        }
+    }
+
+    /// Constructs a hasher converting to grayscale and resizing with implementations owned by this crate.
+    ///
+    /// This is opt-in, since the default steps use the `image` crate, whose output may change between
+    /// its versions and invalidate stored hashes. When enabled, the grayscale conversion uses fixed
+    /// sRGB luma weights and the gray resizer of `with_gray_resizer` is a crate-owned Lanczos3 filter.
+    /// The hashes usually equal the default ones, but may differ in a few bits due to rounding.
+    /// Disabling it restores the grayscale conversion and gray resizer that enabling it replaced.
+    ///
+    /// The other steps still use the `image` crate: decoding in `hash_path`, the 8-bit conversion of
+    /// 16-bit and floating-point sources before the luma weights, the blur of `Preprocess::with_blur`,
+    /// and the luma conversion of later steps with `Preprocess::with_grayscale_disabled`.
+    pub fn with_stable_pipeline(self, stable_pipeline: bool) -> Self {
+        let ((grayscale, gray_resizer), replaced_steps) = stable_pipeline_steps(
+            stable_pipeline,
+            (self.preprocess.grayscale, self.gray_resizer),
+            self.replaced_steps,
+        );
+        AverageHash {
+            preprocess: Preprocess {
+                grayscale,
+                ..self.preprocess
+            },
+            gray_resizer,
+            replaced_steps,
+            ..self
+        }
+    }
+
+    /// Constructs a hasher rotating JPEG files upright by their EXIF orientation in `hash_path`.
+    ///
+    /// The default is `true`. Images passed to `hash` are never rotated.
+    pub fn with_exif_orientation(self, exif_orientation: bool) -> Self {
+        AverageHash {
+            exif_orientation,
+            ..self
+        }
+    }
+
+    /// Constructs a hasher rejecting source images narrower or shorter than `min_source` in `try_hash`.
+    ///
+    /// The default is 0, which allows any source images to be upscaled to the image size.
+    pub fn with_min_source(self, min_source: usize) -> Self {
+        AverageHash { min_source, ..self }
+    }
+
+    preprocess_builders!();
+
+    /// Constructs a hasher with the pivot that the pixels are thresholded against.
+    ///
+    /// The median of 8-bit pixels is found with a 256-bin histogram, so it costs about as much as the mean.
+    pub fn with_pivot(self, pivot: Pivot) -> Self {
+        AverageHash { pivot, ..self }
+    }
+
+    /// Constructs a hasher thresholding each pixel against the mean of its `block` x `block` neighborhood.
+    ///
+    /// The neighborhood is centered on the pixel and clipped at the image edges. This is more robust
+    /// than the global mean for images with lighting gradients.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `block` is less than 2, since a pixel would only be compared with itself and
+    /// every bit would be the same.
+    pub fn with_adaptive(self, block: usize) -> Self {
+        assert!(block >= 2, "adaptive block must be at least 2");
+        AverageHash {
+            adaptive: Some(block),
+            ..self
+        }
+    }
+
+    /// Constructs a hasher weighting the pixels radially by up to `center_weight` in the mean.
+    ///
+    /// The weight falls linearly from `center_weight` at the center of the hash block to 1.0 at its
+    /// corners, so the pivot follows a centered subject more than its background. The default of 1.0
+    /// weighs the pixels uniformly. The weight only moves the mean; each bit still compares its pixel
+    /// with it, since scaling by a positive weight would not change the sign. It has no effect with
+    /// `Pivot::Median` or `with_adaptive`.
+    pub fn with_center_weight(self, center_weight: f64) -> Self {
+        AverageHash {
+            center_weight,
+            ..self
+        }
+    }
+
+    /// Constructs a hasher averaging blocks of the image instead of resizing it.
+    ///
+    /// In this mode, an image at least as large as the hash size is partitioned into `hash_width` x
+    /// `hash_height` blocks, and the mean of each block is thresholded like blockhash. An image of exactly
+    /// the hash size is hashed pixel by pixel. This avoids resampling artifacts for small native images
+    /// such as favicons. Smaller images are resized as usual, and the image size and resizers are
+    /// otherwise ignored.
+    pub fn with_native_blocks(self, native_blocks: bool) -> Self {
+        AverageHash {
+            native_blocks,
+            ..self
+        }
+    }
+
+    /// Calculates the hash of the image file, decoding it reduced to the image size where possible.
+    ///
+    /// The file is decoded by `open_jpeg_scaled` within `default_limits`, or at full resolution like
+    /// `open_image` if a crop or a region detector is set, since their coordinates refer to the full
+    /// image, or if `with_native_blocks` is enabled, since its blocks are averaged from the source pixels.
+    /// Only JPEG files are decoded reduced, with the `jpeg` feature; other formats such as TIFF are
+    /// decoded at full resolution. A reduced JPEG may hash slightly differently from `hash` of the
+    /// same file opened with `open_image`.
+    /// With `ColorSpace::Cmyk` and the `jpeg` feature, CMYK JPEG files are decoded to their ink values
+    /// and converted by the formula of `GrayscaleImage::from_cmyk`.
+    /// JPEG files are then rotated upright by their EXIF orientation unless disabled by
+    /// `with_exif_orientation`, and hashed by `try_hash`, whose errors are returned.
+    pub fn hash_path<Q: AsRef<std::path::Path>>(&self, path: Q) -> Result<Hash, HashError> {
+        let mut image = self
+            .preprocess
+            .open(&path, self.image_size, self.native_blocks)?;
+        if self.exif_orientation {
+            image = orient(image, &path);
+        }
+        self.try_hash(&image)
+    }
+
+    /// Calculates average hash (aHash) of the image and returns as a hex string.
+    pub fn hash(&self, image: &image::DynamicImage) -> Hash {
+        let image = self.preprocess(image);
+        average_hash_core(&image, self)
+    }
+
+    /// Calculates average hash (aHash) of the image, rejecting images that cannot be meaningfully hashed.
+    ///
+    /// Returns `HashError::SourceTooSmall` if the image is smaller than the minimum source size,
+    /// `HashError::LowContrast` if the preprocessed image is nearly constant, or `HashError::NonFinite`
+    /// if a NaN or infinite value reaches the threshold.
+    pub fn try_hash(&self, image: &image::DynamicImage) -> Result<Hash, HashError> {
+        check_source(image, self.min_source)?;
+        let image = self.preprocess(image);
+        image.check_contrast()?;
+        let (values, pivot) = average_hash_values(&image, self);
+        checked_threshold(&values, pivot)
+    }
+
+    /// Calculates average hash (aHash) of the image along with a confidence score.
+    ///
+    /// The score is the mean distance of the thresholded values from the threshold relative to
+    /// their range. Scores near 0.0 indicate flat or ambiguous images whose bits are unreliable.
+    pub fn hash_with_confidence(&self, image: &image::DynamicImage) -> (Hash, f64) {
+        let image = self.preprocess(image);
+        let (values, pivot) = average_hash_values(&image, self);
+        (threshold(&values, pivot), confidence(&values, pivot))
+    }
+
+    /// Calculates the values thresholded in average hash (aHash) of the image, relative to the threshold.
+    ///
+    /// These are the resized pixel values of the hash block minus their mean, or minus their local
+    /// means with `with_adaptive`. The bits of `hash` are set where the values are positive.
+    pub fn features(&self, image: &image::DynamicImage) -> Vec<f64> {
+        let image = self.preprocess(image);
+        let (values, pivot) = average_hash_values(&image, self);
+        values.iter().map(|v| v - pivot).collect()
+    }
+
+    /// Renders the preprocessed image and the hash side by side for debugging.
+    ///
+    /// The left part is the grayscale image after preprocessing and resizing, and the right part
+    /// is the hash block in its top-left corner with white pixels for set bits. Each pixel is scaled
+    /// up to `DEBUG_SCALE` x `DEBUG_SCALE` pixels.
+    pub fn debug_image(&self, image: &image::DynamicImage) -> image::RgbImage {
+        let image = self.preprocess(image);
+        let hash = average_hash_core(&image, self);
+        let (width, height) = (image.width as u32, image.height as u32);
+        let pixels = image.pixels.iter().map(|&v| v.round() as u8).collect();
+        let input = image::GrayImage::from_raw(width, height, pixels).unwrap();
+        let columns = self.hash_size.0.min(image.width);
+        let rows = self.hash_size.1.min(image.height);
+        let grid = hash.to_image(columns, rows).unwrap();
+        let mut result =
+            image::RgbImage::new((width + columns as u32) * DEBUG_SCALE, height * DEBUG_SCALE);
+        for (x, y, pixel) in result.enumerate_pixels_mut() {
+            let (column, row) = (x / DEBUG_SCALE, y / DEBUG_SCALE);
+            let value = if column < width {
+                input.get_pixel(column, row)[0]
+            } else if row < rows as u32 {
+                grid.get_pixel(column - width, row)[0]
+            } else {
+                0
+            };
+            *pixel = image::Rgb([value, value, value]);
+        }
+        result
+    }
+
+    /// Calculates average hash (aHash) of the grayscale image without preprocessing or resizing, rejecting
+    /// images that cannot be meaningfully hashed.
+    ///
+    /// Returns `HashError::LowContrast` if the image is nearly constant, or `HashError::NonFinite`
+    /// if the image has NaN or infinite pixels.
+    pub fn try_hash_gray<P: Into<f64> + Copy>(
+        &self,
+        image: &GrayscaleImage<P>,
+    ) -> Result<Hash, HashError> {
+        image.check_contrast()?;
+        let (values, pivot) = average_hash_values(image, self);
+        checked_threshold(&values, pivot)
+    }
+
+    /// Calculates average hash (aHash) of the grayscale image without preprocessing or resizing.
+    pub fn hash_gray<P: Into<f64> + Copy>(&self, image: &GrayscaleImage<P>) -> Hash {
+        average_hash_core(image, self)
+    }
+
+    fn preprocess(&self, image: &image::DynamicImage) -> GrayscaleImage<f32> {
+        let image = self.resize(self.preprocess.apply(image));
+        self.preprocess.finish(image)
+    }
+
+    /// Resizes the image after the steps before resizing to the image size.
+    fn resize(&self, image: image::DynamicImage) -> GrayscaleImage {
+        let (hash_width, hash_height) = self.hash_size;
+        if self.native_blocks
+            && image.width() as usize >= hash_width
+            && image.height() as usize >= hash_height
+        {
+            return block_means(&image.into_luma8(), hash_width, hash_height);
+        }
+        match self.gray_resizer {
+            Some(gray_resizer) => {
+                gray_resizer(&image.into_luma8(), self.image_size.0, self.image_size.1).into()
+            }
+            None => (self.resizer)(&image, self.image_size.0, self.image_size.1).into(),
+        }
+    }
+}
+
+impl ImageHasher for AverageHash {
+    fn hash(&self, image: &image::DynamicImage) -> Hash {
+        AverageHash::hash(self, image)
+    }
+
+    fn hash_gray(&self, image: &GrayscaleImage) -> Hash {
+        AverageHash::hash_gray(self, image)
+    }
+}
+
+impl Default for AverageHash {
+    /// Creates a new `AverageHasher` with default parameters.
+    fn default() -> Self {
+        AverageHash {
+            image_size: (8, 8),
+            hash_size: (8, 8),
+            resizer: resize,
+            gray_resizer: None,
+            replaced_steps: None,
+            preprocess: Preprocess::default(),
+            adaptive: None,
+            pivot: Pivot::default(),
+            min_source: 0,
+            exif_orientation: true,
+            native_blocks: false,
+            center_weight: 1.0,
+        }
+    }
+}
+
+/// Partitions the image into `width` x `height` blocks and returns the rounded mean of each block.
+///
+/// The block boundaries are rounded down, so the blocks differ in size by at most one pixel.
+fn block_means(image: &image::GrayImage, width: usize, height: usize) -> GrayscaleImage {
+    let (w, h) = (image.width() as usize, image.height() as usize);
+    let raw = image.as_raw();
+    let mut pixels = Vec::with_capacity(width * height);
+    for by in 0..height {
+        let (top, bottom) = (by * h / height, (by + 1) * h / height);
+        for bx in 0..width {
+            let (left, right) = (bx * w / width, (bx + 1) * w / width);
+            let mut sum = 0u64;
+            for y in top..bottom {
+                sum += raw[y * w + left..y * w + right]
+                    .iter()
+                    .map(|&v| u64::from(v))
+                    .sum::<u64>();
+            }
+            let count = ((bottom - top) * (right - left)) as u64;
+            pixels.push(((sum + count / 2) / count) as u8);
+        }
+    }
+    GrayscaleImage::new(pixels, width, height)
+}
+
+/// The scale of each pixel in `AverageHash::debug_image`.
+const DEBUG_SCALE: u32 = 16;
+
+/// Provides average hash (aHash) calculation of a `W` x `H` hash fixed at compile time.
+///
+/// The hash is returned as `HashN`, which stores its bits inline. Only the bit storage is
+/// allocation-free: the preprocessed and resized grayscale image is still allocated for each hash.
+/// The image is resized to `W` x `H`, so the hashes equal those of `AverageHash` with both the
+/// image size and the hash size set to `W` x `H`, and its default mean pivot with uniform weights.
+pub struct AverageHashN<const W: usize, const H: usize> {
+    inner: AverageHash,
+}
+
+impl<const W: usize, const H: usize> AverageHashN<W, H> {
+    /// Creates a new `AverageHashN` with default parameters.
+    pub fn new() -> Self {
+        AverageHashN::default()
+    }
+
+    /// Constructs a hasher with the preprocessing steps.
+    pub fn with_preprocess(self, preprocess: Preprocess) -> Self {
+        AverageHashN {
+            inner: self.inner.with_preprocess(preprocess),
+        }
+    }
+
+    /// Calculates average hash (aHash) of the image.
+    pub fn hash(&self, image: &image::DynamicImage) -> HashN<W, H> {
+        let image = self.inner.preprocess(image);
+        let mean = image.pixels.iter().map(|&v| f64::from(v)).sum::<f64>() / (W * H) as f64;
+        let mut bits = [[false; W]; H];
+        for (y, row) in bits.iter_mut().enumerate() {
+            for (x, bit) in row.iter_mut().enumerate() {
+                *bit = f64::from(image.pixels[y * W + x]) > mean;
+            }
+        }
+        HashN { bits }
+    }
+}
+
+impl<const W: usize, const H: usize> ImageHasher for AverageHashN<W, H> {
+    fn hash(&self, image: &image::DynamicImage) -> Hash {
+        AverageHashN::hash(self, image).into()
+    }
+
+    fn hash_gray(&self, image: &GrayscaleImage) -> Hash {
+        self.inner.hash_gray(image)
+    }
+}
+
+impl<const W: usize, const H: usize> Default for AverageHashN<W, H> {
+    /// Creates a new `AverageHashN` with default parameters.
+    fn default() -> Self {
+        AverageHashN {
+            inner: AverageHash::new()
+                .with_image_size(W, H)
+                .with_hash_size(W, H),
+        }
+    }
+}
+
+/// Provides average hash (aHash) calculation from block sums fed one block at a time.
+///
+/// Each block becomes one bit, set if the mean of the block is greater than the mean of the block
+/// means, so tile pipelines can hash an image without holding it whole. The blocks are added in
+/// the order of the bits, row-major for the usual layout. Feeding the blocks of `AverageHash` with
+/// `with_native_blocks` gives the same hash up to the rounding of its block means.
+#[derive(Debug, Clone, Default)]
+pub struct IncrementalAverageHash {
+    means: Vec<f64>,
+}
+
+impl IncrementalAverageHash {
+    /// Creates a new `IncrementalAverageHash` with no blocks.
+    pub fn new() -> Self {
+        IncrementalAverageHash::default()
+    }
+
+    /// Adds the block whose `count` pixels sum to `sum`.
+    ///
+    /// A block of no pixels has the mean 0.
+    pub fn add_block(&mut self, sum: f64, count: usize) {
+        self.means
+            .push(if count > 0 { sum / count as f64 } else { 0.0 });
+    }
+
+    /// Returns the number of blocks added.
+    pub fn len(&self) -> usize {
+        self.means.len()
+    }
+
+    /// Returns whether no blocks have been added.
+    pub fn is_empty(&self) -> bool {
+        self.means.is_empty()
+    }
+
+    /// Returns the hash of one bit per block added.
+    pub fn finish(&self) -> Hash {
+        if self.means.is_empty() {
+            return Hash { bits: Vec::new() };
+        }
+        let pivot = self.means.iter().sum::<f64>() / self.means.len() as f64;
+        threshold(&self.means, pivot)
+    }
+}
+
+/// Calculates average hash (aHash) of the image.
+pub fn average_hash(image: &image::DynamicImage) -> Hash {
+    let image: GrayscaleImage = resize(&image.grayscale(), 8, 8).into();
+    average_hash_core(&image, &AverageHash::default())
+}
+
+/// Calculates average hash (aHash) of the image file.
+///
+/// The image format must be enabled in the features of the `image` crate. The image is decoded within
+/// `default_limits`, and `HashError::TooLarge` is returned if it exceeds them. JPEG files are rotated
+/// upright by their EXIF orientation.
+pub fn average_hash_path<P: AsRef<std::path::Path>>(path: P) -> Result<Hash, HashError> {
+    let image = open_image(&path, default_limits())?;
+    Ok(average_hash(&orient(image, &path)))
+}
+
+fn average_hash_core<P: Into<f64> + Copy>(image: &GrayscaleImage<P>, hasher: &AverageHash) -> Hash {
+    let (values, pivot) = average_hash_values(image, hasher);
+    threshold(&values, pivot)
+}
+
+/// Returns the values thresholded in average hash (aHash) and their pivot.
+fn average_hash_values<P: Into<f64> + Copy>(
+    image: &GrayscaleImage<P>,
+    hasher: &AverageHash,
+) -> (Vec<f64>, f64) {
+    let (hash_width, hash_height) = hasher.hash_size;
+    if let Some(block) = hasher.adaptive {
+        let deviations = local_deviations(image, block);
+        return (
+            select_block(&deviations, image.width, 0, hash_width, hash_height),
+            0.0,
+        );
+    }
+    let pixels: Vec<f64> = image.iter_pixels().collect();
+    let block = select_block(&pixels, image.width, 0, hash_width, hash_height);
+    let pivot = match hasher.pivot {
+        Pivot::Mean if hasher.center_weight != 1.0 => {
+            let columns = hash_width.min(image.width);
+            let rows = hash_height.min(image.height);
+            let weights = center_weights(columns, rows, hasher.center_weight);
+            let (mut sum, mut total) = (0.0, 0.0);
+            for (v, w) in block.iter().zip(weights) {
+                sum += v * w;
+                total += w;
+            }
+            sum / total
+        }
+        Pivot::Mean => block.iter().sum::<f64>() / block.len() as f64,
+        Pivot::Median => level_median(&block),
+    };
+    (block, pivot)
+}
+
+/// Returns the row-major weights of the `width` x `height` block, falling linearly from `center_weight`
+/// at its center to 1.0 at its corners.
+fn center_weights(width: usize, height: usize, center_weight: f64) -> Vec<f64> {
+    let offset = |i: usize, n: usize| {
+        if n > 1 {
+            let c = (n - 1) as f64 / 2.0;
+            (i as f64 - c) / c
+        } else {
+            0.0
+        }
+    };
+    let mut weights = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let (dx, dy) = (offset(x, width), offset(y, height));
+            let r = ((dx * dx + dy * dy) / 2.0).sqrt();
+            weights.push(center_weight + (1.0 - center_weight) * r);
+        }
+    }
+    weights
+}
+
+/// Returns the difference of each pixel from the mean of its `block` x `block` neighborhood.
+fn local_deviations<P: Into<f64> + Copy>(image: &GrayscaleImage<P>, block: usize) -> Vec<f64> {
+    let pixels: Vec<f64> = image.iter_pixels().collect();
+    let mut result = Vec::with_capacity(pixels.len());
+    for y in 0..image.height {
+        let (top, bottom) = (
+            y.saturating_sub(block / 2),
+            (y + block - block / 2).min(image.height),
+        );
+        for x in 0..image.width {
+            let (left, right) = (
+                x.saturating_sub(block / 2),
+                (x + block - block / 2).min(image.width),
+            );
+            let (mut sum, mut count) = (0.0, 0);
+            for ny in top..bottom {
+                for nx in left..right {
+                    sum += pixels[ny * image.width + nx];
+                    count += 1;
+                }
+            }
+            let mean = if count > 0 { sum / count as f64 } else { 0.0 };
+            result.push(pixels[y * image.width + x] - mean);
+        }
+    }
+    result
+}
+
+/// Calculates average hash (aHash) of the RGBA8 pixels in row-major order, such as a canvas `ImageData` buffer.
+///
+/// # Panics
+///
+/// Panics if the length of `pixels` is not `width * height * 4`.
+pub fn average_hash_rgba(pixels: &[u8], width: usize, height: usize) -> Hash {
+    let image = rgba_to_gray(pixels, width, height);
+    let image: GrayscaleImage =
+        image::imageops::resize(&image, 8, 8, image::imageops::FilterType::Lanczos3).into();
+    average_hash_core(&image, &AverageHash::default())
+}
+
+/// Provides difference hash (dHash) calculation.
+pub struct DifferenceHash {
+    image_size: (usize, usize),
+    hash_size: (usize, usize),
+    resizer: fn(&image::DynamicImage, usize, usize) -> image::DynamicImage,
+    gray_resizer: Option<GrayResizer>,
+    replaced_steps: Option<PipelineSteps>,
+    preprocess: Preprocess,
+    min_source: usize,
+    exif_orientation: bool,
+    ignore_transparent: bool,
+}
+
+impl DifferenceHash {
+    /// Creates a new `DifferenceHasher` with default parameters.
+    pub fn new() -> Self {
+        DifferenceHash::default()
+    }
+
+    /// Constructs a hasher with the image size.
+    pub fn with_image_size(self, width: usize, height: usize) -> Self {
+        DifferenceHash {
+            image_size: (width, height),
+            ..self
+        }
+    }
+
+    /// Constructs a hasher with the hash size.
+    pub fn with_hash_size(self, width: usize, height: usize) -> Self {
+        DifferenceHash {
+            hash_size: (width, height),
+            ..self
+        }
+    }
+
+    /// Constructs a hasher with the square image size of `n` x `n`.
+    pub fn with_square_image_size(self, n: usize) -> Self {
+        self.with_image_size(n, n)
+    }
+
+    /// Constructs a hasher with the square hash size of `n` x `n`.
+    pub fn with_square_hash_size(self, n: usize) -> Self {
+        self.with_hash_size(n, n)
+    }
+
+    /// Constructs a hasher with the resizer function.
+    ///
+    /// The resizer receives the preprocessed grayscale image. Setting it overrides `with_gray_resizer`.
+    pub fn with_resizer(
+        self,
+        resizer: fn(&image::DynamicImage, usize, usize) -> image::DynamicImage,
+    ) -> Self {
+        DifferenceHash {
+            resizer,
+            gray_resizer: None,
+            ..self
+        }
+    }
+
+    /// Constructs a hasher with the resizer function operating on the preprocessed grayscale image.
+    ///
+    /// This avoids converting between `DynamicImage` and `GrayImage` around resizing.
+    /// Setting it overrides `with_resizer`.
+    pub fn with_gray_resizer(self, gray_resizer: GrayResizer) -> Self {
+        DifferenceHash {
+            gray_resizer: Some(gray_resizer),
+            ..self
+        }
+    }
+
+    /// Constructs a hasher downscaling with `image::imageops::thumbnail` instead of the resizer function.
+    ///
+    /// This is much faster than the default Lanczos3 filter for large source images, at the cost of
+    /// different hashes. It sets the gray resizer of `with_gray_resizer` when enabled and clears it otherwise.
+    pub fn with_thumbnail_resize(self, thumbnail_resize: bool) -> Self {
+        if thumbnail_resize {
+            self.with_gray_resizer(thumbnail)
+        } else {
+            DifferenceHash {
+                gray_resizer: None,
+                ..self
+            }
+        }
+    }
+
+    /// Constructs a hasher converting to grayscale and resizing with implementations owned by this crate.
+    ///
+    /// See `AverageHash::with_stable_pipeline` for the steps it covers.
+    pub fn with_stable_pipeline(self, stable_pipeline: bool) -> Self {
+        let ((grayscale, gray_resizer), replaced_steps) = stable_pipeline_steps(
+            stable_pipeline,
+            (self.preprocess.grayscale, self.gray_resizer),
+            self.replaced_steps,
+        );
+        DifferenceHash {
+            preprocess: Preprocess {
+                grayscale,
+                ..self.preprocess
+            },
+            gray_resizer,
+            replaced_steps,
+            ..self
+        }
+    }
+
+    /// Constructs a hasher rotating JPEG files upright by their EXIF orientation in `hash_path`.
+    ///
+    /// The default is `true`. Images passed to `hash` are never rotated.
+    pub fn with_exif_orientation(self, exif_orientation: bool) -> Self {
+        DifferenceHash {
+            exif_orientation,
+            ..self
+        }
+    }
+
+    /// Constructs a hasher rejecting source images narrower or shorter than `min_source` in `try_hash`.
+    ///
+    /// The default is 0, which allows any source images to be upscaled to the image size.
+    pub fn with_min_source(self, min_source: usize) -> Self {
+        DifferenceHash { min_source, ..self }
+    }
+
+    preprocess_builders!();
+
+    /// Constructs a hasher treating pixels that are fully transparent after resizing as having no edge.
+    ///
+    /// The colors of fully transparent pixels are replaced with the mean color of the other pixels before
+    /// preprocessing. The alpha channel is cropped like the image and resized to the image size, and each
+    /// gradient touching a resized pixel of alpha 0 clears its bit. This keeps the hash of a logo from
+    /// depending on its transparent background and the colors hidden under it. Images without an alpha
+    /// channel are hashed as usual.
+    pub fn with_ignore_transparent(self, ignore_transparent: bool) -> Self {
+        DifferenceHash {
+            ignore_transparent,
+            ..self
+        }
+    }
+
+    /// Calculates the hash of the image file, decoding it reduced to the image size where possible.
+    ///
+    /// The file is decoded by `open_jpeg_scaled` within `default_limits`, or at full resolution like
+    /// `open_image` if a crop or a region detector is set, since their coordinates refer to the full image.
+    /// Only JPEG files are decoded reduced, with the `jpeg` feature; other formats such as TIFF are
+    /// decoded at full resolution. A reduced JPEG may hash slightly differently from `hash` of the
+    /// same file opened with `open_image`.
+    /// With `ColorSpace::Cmyk` and the `jpeg` feature, CMYK JPEG files are decoded to their ink values
+    /// and converted by the formula of `GrayscaleImage::from_cmyk`.
+    /// JPEG files are then rotated upright by their EXIF orientation unless disabled by
+    /// `with_exif_orientation`, and hashed by `try_hash`, whose errors are returned.
+    pub fn hash_path<Q: AsRef<std::path::Path>>(&self, path: Q) -> Result<Hash, HashError> {
+        let mut image = self.preprocess.open(&path, self.image_size, false)?;
+        if self.exif_orientation {
+            image = orient(image, &path);
+        }
+        self.try_hash(&image)
+    }
+
+    /// Calculates difference hash (dHash) of the image and returns as a hex string.
+    pub fn hash(&self, image: &image::DynamicImage) -> Hash {
+        let (_, values, pivot) = self.preprocess_values(image);
+        threshold(&values, pivot)
+    }
+
+    /// Calculates difference hash (dHash) of the image, rejecting images that cannot be meaningfully hashed.
+    ///
+    /// Returns `HashError::SourceTooSmall` if the image is smaller than the minimum source size,
+    /// `HashError::LowContrast` if the preprocessed image is nearly constant, or `HashError::NonFinite`
+    /// if a NaN or infinite value reaches the threshold.
+    pub fn try_hash(&self, image: &image::DynamicImage) -> Result<Hash, HashError> {
+        check_source(image, self.min_source)?;
+        let (gray, values, pivot) = self.preprocess_values(image);
+        gray.check_contrast()?;
+        checked_threshold(&values, pivot)
+    }
+
+    /// Calculates difference hash (dHash) of the image along with a confidence score.
+    ///
+    /// The score is the mean distance of the thresholded values from the threshold relative to
+    /// their range. Scores near 0.0 indicate flat or ambiguous images whose bits are unreliable.
+    pub fn hash_with_confidence(&self, image: &image::DynamicImage) -> (Hash, f64) {
+        let (_, values, pivot) = self.preprocess_values(image);
+        (threshold(&values, pivot), confidence(&values, pivot))
+    }
+
+    /// Calculates the values thresholded in difference hash (dHash) of the image.
+    ///
+    /// These are the differences between horizontally adjacent resized pixels. The bits of `hash`
+    /// are set where the values are positive.
+    pub fn features(&self, image: &image::DynamicImage) -> Vec<f64> {
+        self.preprocess_values(image).1
+    }
+
+    /// Calculates difference hash (dHash) of the grayscale image without preprocessing or resizing, rejecting
+    /// images that cannot be meaningfully hashed.
+    ///
+    /// Returns `HashError::LowContrast` if the image is nearly constant, or `HashError::NonFinite`
+    /// if the image has NaN or infinite pixels.
+    pub fn try_hash_gray<P: Into<f64> + Copy>(
+        &self,
+        image: &GrayscaleImage<P>,
+    ) -> Result<Hash, HashError> {
+        image.check_contrast()?;
+        let (values, pivot) = difference_hash_values(image, self.hash_size.0, self.hash_size.1);
+        checked_threshold(&values, pivot)
+    }
+
+    /// Calculates difference hash (dHash) of the grayscale image without preprocessing or resizing.
+    pub fn hash_gray<P: Into<f64> + Copy>(&self, image: &GrayscaleImage<P>) -> Hash {
+        difference_hash_core(image, self.hash_size.0, self.hash_size.1)
+    }
+
+    /// Returns the preprocessed image and its values thresholded in dHash with their pivot.
+    ///
+    /// The values of gradients touching transparent pixels are set to the pivot if `ignore_transparent` is enabled.
+    fn preprocess_values(
+        &self,
+        image: &image::DynamicImage,
+    ) -> (GrayscaleImage<f32>, Vec<f64>, f64) {
+        let masked = self.ignore_transparent && image.color().has_alpha();
+        let filled;
+        let image = if masked {
+            filled = fill_transparent(image);
+            &filled
+        } else {
+            image
+        };
+        let cropped = self.preprocess.cropped(image);
+        let gray = self
+            .preprocess
+            .finish(self.resize(self.preprocess.apply_cropped(&cropped)));
+        let (hash_width, hash_height) = self.hash_size;
+        let (mut values, pivot) = difference_hash_values(&gray, hash_width, hash_height);
+        if masked {
+            let alpha = image::imageops::resize(
+                &self.preprocess.alpha(&cropped),
+                gray.width as u32,
+                gray.height as u32,
+                image::imageops::FilterType::Triangle,
+            );
+            let transparent = |x: usize, y: usize| alpha.get_pixel(x as u32, y as u32)[0] == 0;
+            let width = hash_width.min(gray.width.saturating_sub(1));
+            for y in 0..hash_height.min(gray.height) {
+                for x in 0..width {
+                    if transparent(x, y) || transparent(x + 1, y) {
+                        values[y * width + x] = pivot;
+                    }
+                }
+            }
+        }
+        (gray, values, pivot)
+    }
+
+    /// Resizes the image after the steps before resizing to the image size.
+    fn resize(&self, image: image::DynamicImage) -> GrayscaleImage {
+        match self.gray_resizer {
+            Some(gray_resizer) => {
+                gray_resizer(&image.into_luma8(), self.image_size.0, self.image_size.1).into()
+            }
+            None => (self.resizer)(&image, self.image_size.0, self.image_size.1).into(),
+        }
+    }
+}
+
+impl ImageHasher for DifferenceHash {
+    fn hash(&self, image: &image::DynamicImage) -> Hash {
+        DifferenceHash::hash(self, image)
+    }
+
+    fn hash_gray(&self, image: &GrayscaleImage) -> Hash {
+        DifferenceHash::hash_gray(self, image)
+    }
+}
+
+impl Default for DifferenceHash {
+    /// Creates a new `DifferenceHasher` with default parameters.
+    fn default() -> Self {
+        DifferenceHash {
+            image_size: (9, 8),
+            hash_size: (8, 8),
+            resizer: resize,
+            gray_resizer: None,
+            replaced_steps: None,
+            preprocess: Preprocess::default(),
+            min_source: 0,
+            exif_orientation: true,
+            ignore_transparent: false,
+        }
+    }
+}
+
+/// Provides the concatenation of average hash (aHash) and difference hash (dHash) of the same image.
+///
+/// aHash and dHash are sensitive to different transformations, so the distance of the composite
+/// hashes is more robust than either. The bits of aHash come first, followed by the bits of dHash,
+/// so `split_at` at the `hash_width * hash_height` of the aHash hasher separates them whenever its
+/// image size is at least its hash size.
+pub struct CompositeHash {
+    average: AverageHash,
+    difference: DifferenceHash,
+    preprocess: Preprocess,
+}
+
+impl CompositeHash {
+    /// Creates a new `CompositeHash` with default parameters.
+    pub fn new() -> Self {
+        CompositeHash::default()
+    }
+
+    /// Constructs a hasher with the aHash hasher for the image sizes, resizers and the pivot.
+    ///
+    /// The preprocessing of the aHash hasher is ignored in favor of that of `with_preprocess`.
+    pub fn with_average_hash(self, average: AverageHash) -> Self {
+        CompositeHash { average, ..self }
+    }
+
+    /// Constructs a hasher with the dHash hasher for the image sizes and resizers.
+    ///
+    /// The preprocessing of the dHash hasher is ignored in favor of that of `with_preprocess`.
+    pub fn with_difference_hash(self, difference: DifferenceHash) -> Self {
+        CompositeHash { difference, ..self }
+    }
+
+    /// Constructs a hasher with the preprocessing steps, which are applied once for both hashes.
+    pub fn with_preprocess(self, preprocess: Preprocess) -> Self {
+        CompositeHash { preprocess, ..self }
+    }
+
+    /// Calculates the composite hash of the image.
+    ///
+    /// The image is preprocessed once, and then resized to the image sizes of both hashers.
+    pub fn hash(&self, image: &image::DynamicImage) -> Hash {
+        let image = self.preprocess.apply(image);
+        let average = self.preprocess.finish(self.average.resize(image.clone()));
+        let difference = self.preprocess.finish(self.difference.resize(image));
+        let (hash_width, hash_height) = self.difference.hash_size;
+        let mut bits = average_hash_core(&average, &self.average).bits;
+        bits.extend(difference_hash_core(&difference, hash_width, hash_height).bits);
+        Hash { bits }
+    }
+
+    /// Calculates the composite hash of the grayscale image without preprocessing or resizing.
+    pub fn hash_gray<P: Into<f64> + Copy>(&self, image: &GrayscaleImage<P>) -> Hash {
+        let mut bits = self.average.hash_gray(image).bits;
+        bits.extend(self.difference.hash_gray(image).bits);
+        Hash { bits }
+    }
+}
+
+impl ImageHasher for CompositeHash {
+    fn hash(&self, image: &image::DynamicImage) -> Hash {
+        CompositeHash::hash(self, image)
+    }
+
+    fn hash_gray(&self, image: &GrayscaleImage) -> Hash {
+        CompositeHash::hash_gray(self, image)
+    }
+}
+
+impl Default for CompositeHash {
+    /// Creates a new `CompositeHash` with default parameters.
+    fn default() -> Self {
+        CompositeHash {
+            average: AverageHash::default(),
+            difference: DifferenceHash::default(),
+            preprocess: Preprocess::default(),
+        }
+    }
+}
+
+/// Calculates difference hash (dHash) of the image.
+pub fn difference_hash(image: &image::DynamicImage) -> Hash {
+    let image: GrayscaleImage = resize(&image.grayscale(), 9, 8).into();
+    difference_hash_core(&image, 8, 8)
+}
+
+/// Calculates difference hash (dHash) of the image file.
+///
+/// The image format must be enabled in the features of the `image` crate. The image is decoded within
+/// `default_limits`, and `HashError::TooLarge` is returned if it exceeds them. JPEG files are rotated
+/// upright by their EXIF orientation.
+pub fn difference_hash_path<P: AsRef<std::path::Path>>(path: P) -> Result<Hash, HashError> {
+    let image = open_image(&path, default_limits())?;
+    Ok(difference_hash(&orient(image, &path)))
+}
+
+fn difference_hash_core<P: Into<f64> + Copy>(
+    image: &GrayscaleImage<P>,
+    hash_width: usize,
+    hash_height: usize,
+) -> Hash {
+    let (values, pivot) = difference_hash_values(image, hash_width, hash_height);
+    threshold(&values, pivot)
+}
+
+/// Returns the values thresholded in difference hash (dHash) and their pivot.
+fn difference_hash_values<P: Into<f64> + Copy>(
+    image: &GrayscaleImage<P>,
+    hash_width: usize,
+    hash_height: usize,
+) -> (Vec<f64>, f64) {
+    let values = image
+        .iter_rows()
+        .take(hash_height)
+        .flat_map(|row| {
+            row.collect::<Vec<f64>>()
+                .windows(2)
+                .take(hash_width)
+                .map(|w| w[1] - w[0])
+                .collect::<Vec<f64>>()
+        })
+        .collect();
+    (values, 0.0)
+}
+
+/// Replaces the colors of the fully transparent pixels with the mean color of the other pixels.
+///
+/// The hidden colors would otherwise bleed into the resized pixels around the transparent regions.
+fn fill_transparent(image: &image::DynamicImage) -> image::DynamicImage {
+    let mut rgba = image.to_rgba8();
+    let (mut sum, mut count) = ([0u64; 3], 0u64);
+    for pixel in rgba.pixels().filter(|p| p[3] > 0) {
+        for (s, &c) in sum.iter_mut().zip(pixel.0.iter()) {
+            *s += u64::from(c);
+        }
+        count += 1;
+    }
+    let mean = sum.map(|s| s.checked_div(count).unwrap_or(0) as u8);
+    for pixel in rgba.pixels_mut().filter(|p| p[3] == 0) {
+        *pixel = image::Rgba([mean[0], mean[1], mean[2], 0]);
+    }
+    image::DynamicImage::ImageRgba8(rgba)
+}
+
+/// Provides perceptual hash (pHash) calculation.
+pub struct PerceptualHash {
+    image_size: (usize, usize),
+    hash_size: (usize, usize),
+    resizer: fn(&image::DynamicImage, usize, usize) -> image::DynamicImage,
+    gray_resizer: Option<GrayResizer>,
+    replaced_steps: Option<PipelineSteps>,
+    preprocess: Preprocess,
+    transform: TransformKind,
+    zigzag: bool,
+    column_transform: bool,
+    pivot: Pivot,
+    drop_low: usize,
+    resize_filter: Option<image::imageops::FilterType>,
+    transpose_output: bool,
+    zero_pivot: bool,
+    exclude_dc_only: bool,
+    dct_length: Option<usize>,
+    top_k: Option<usize>,
+    weights: Option<Vec<f64>>,
+    color: bool,
+    min_source: usize,
+    exif_orientation: bool,
+    oversample: usize,
+}
+
+impl PerceptualHash {
+    /// Creates a new `PerceptualHasher` with default parameters.
+    pub fn new() -> Self {
+        PerceptualHash::default()
+    }
+
+    /// Creates a new `PerceptualHasher` reproducing `phash_simple` of the Python imagehash library.
+    ///
+    /// It applies the DCT to each row of the 32x32 image, selects the 8x8 block after the first
+    /// column and thresholds it against its mean. The defaults of this crate were taken from
+    /// `phash_simple`, so this is equivalent to `PerceptualHash::new()`, but it keeps matching
+    /// `phash_simple` if the defaults change. See `two_dimensional` for `phash`.
+    /// The bits agree for images already at 32x32 in grayscale; other images are decoded and resized
+    /// differently from Pillow, so their borderline bits may differ.
+    pub fn simple() -> Self {
+        PerceptualHash {
+            image_size: (32, 32),
+            hash_size: (8, 8),
+            transform: TransformKind::Dct2,
+            zigzag: false,
+            column_transform: false,
+            pivot: Pivot::Mean,
+            drop_low: 1,
+            ..PerceptualHash::default()
+        }
+    }
+
+    /// Creates a new `PerceptualHasher` reproducing `phash` of the Python imagehash library.
+    ///
+    /// It applies the DCT to the rows and then the columns of the 32x32 image, selects the top-left
+    /// 8x8 block including the DC term and thresholds it against its median. As with `simple`, the
+    /// bits agree for images already at 32x32 in grayscale.
+    pub fn two_dimensional() -> Self {
+        PerceptualHash {
+            column_transform: true,
+            pivot: Pivot::Median,
+            drop_low: 0,
+            ..PerceptualHash::simple()
+        }
+    }
+
+    /// Constructs a hasher with the image size.
+    pub fn with_image_size(self, width: usize, height: usize) -> Self {
+        PerceptualHash {
+            image_size: (width, height),
+            ..self
+        }
+    }
+
+    /// Constructs a hasher with the hash size.
+    pub fn with_hash_size(self, width: usize, height: usize) -> Self {
+        PerceptualHash {
+            hash_size: (width, height),
+            ..self
+        }
+    }
+
+    /// Constructs a hasher with the square image size of `n` x `n`.
+    pub fn with_square_image_size(self, n: usize) -> Self {
+        self.with_image_size(n, n)
+    }
+
+    /// Constructs a hasher with the square hash size of `n` x `n`.
+    pub fn with_square_hash_size(self, n: usize) -> Self {
+        self.with_hash_size(n, n)
+    }
+
+    /// Constructs a hasher with the resizer function.
+    ///
+    /// The resizer receives the preprocessed grayscale image. Setting it overrides
+    /// `with_gray_resizer` and `with_resize_filter`.
+    pub fn with_resizer(
+        self,
+        resizer: fn(&image::DynamicImage, usize, usize) -> image::DynamicImage,
+    ) -> Self {
+        PerceptualHash {
+            resizer,
+            gray_resizer: None,
+            resize_filter: None,
+            ..self
+        }
+    }
+
+    /// Constructs a hasher with the resizer function operating on the preprocessed grayscale image.
+    ///
+    /// This avoids converting between `DynamicImage` and `GrayImage` around resizing.
+    /// Setting it overrides `with_resizer` and `with_resize_filter`.
+    pub fn with_gray_resizer(self, gray_resizer: GrayResizer) -> Self {
+        PerceptualHash {
+            gray_resizer: Some(gray_resizer),
+            resize_filter: None,
+            ..self
+        }
+    }
+
+    /// Constructs a hasher multiplying the selected coefficients by the weights before thresholding.
+    ///
+    /// The weights are in the order of the hash bits, row-major over the `hash_width` x `hash_height`
+    /// block by default, and the pivot is computed over the weighted coefficients. Like a JPEG
+    /// quantization table, small weights de-emphasize noisy frequencies. The weights are ignored with
+    /// `with_top_k`.
+    ///
+    /// `try_hash` returns `HashError::WeightMismatch` if the number of weights differs from the number
+    /// of selected coefficients, and the other methods panic.
+    pub fn with_weight_matrix(self, weights: Vec<f64>) -> Self {
+        PerceptualHash {
+            weights: Some(weights),
+            ..self
+        }
+    }
+
+    /// Constructs a hasher encoding the positions and signs of the `k` coefficients with the largest magnitudes.
+    ///
+    /// The candidates are all coefficients of the transformed rows, and columns with
+    /// `with_column_transform`, except the first `drop_low` columns, in row-major order, instead of
+    /// the hash-size block. For `n` candidates the hash has `2 * n` bits: bit `i` is set if candidate
+    /// `i` is among the top `k`, and bit `n + i` is set if it is also positive. The Hamming distance of these hashes counts the coefficients that moved
+    /// in or out of the top `k` and those that flipped their signs. `with_zigzag`,
+    /// `with_transpose_output` and `with_pivot` have no effect in this mode.
+    pub fn with_top_k(self, k: usize) -> Self {
+        PerceptualHash {
+            top_k: Some(k),
+            ..self
+        }
+    }
+
+    /// Constructs a hasher transforming rows of `dct_length` values instead of the image width.
+    ///
+    /// Rows are zero-padded when `dct_length` exceeds the image width and truncated otherwise.
+    /// The low-frequency block is selected from the first columns of the transformed rows, so a
+    /// longer length selects a narrower band of lower frequencies. `dct_length` must be at least
+    /// `hash_width + drop_low` for the block to fill the hash.
+    pub fn with_dct_length(self, dct_length: usize) -> Self {
+        PerceptualHash {
+            dct_length: Some(dct_length),
+            ..self
+        }
+    }
+
+    /// Constructs a hasher subtracting the mean from the image before the transform and thresholding
+    /// the coefficients against zero.
+    ///
+    /// This is the classic pHash formulation. It overrides `with_pivot`.
+    pub fn with_zero_pivot(self, zero_pivot: bool) -> Self {
+        PerceptualHash { zero_pivot, ..self }
+    }
+
+    /// Constructs a hasher transposing the selected coefficient block before flattening it to bits.
+    ///
+    /// This produces column-major bits for tools with rows and columns swapped. It has no effect with `with_zigzag`.
+    pub fn with_transpose_output(self, transpose_output: bool) -> Self {
+        PerceptualHash {
+            transpose_output,
+            ..self
+        }
+    }
+
+    /// Constructs a hasher resizing with the filter instead of the resizer function.
+    ///
+    /// pHash is sensitive to aliasing, so smoothing filters such as `Triangle`, `CatmullRom` and
+    /// `Lanczos3` (the default) are recommended, while `Nearest` is not. Setting a resizer function
+    /// afterwards overrides the filter.
+    pub fn with_resize_filter(self, filter: image::imageops::FilterType) -> Self {
+        PerceptualHash {
+            resize_filter: Some(filter),
+            gray_resizer: None,
+            ..self
+        }
+    }
+
+    /// Constructs a hasher downscaling with `image::imageops::thumbnail` instead of the resizer function.
+    ///
+    /// This is much faster than the default Lanczos3 filter for large source images, at the cost of
+    /// different hashes. It sets the gray resizer of `with_gray_resizer` when enabled and clears it otherwise.
+    pub fn with_thumbnail_resize(self, thumbnail_resize: bool) -> Self {
+        if thumbnail_resize {
+            self.with_gray_resizer(thumbnail)
+        } else {
+            PerceptualHash {
+                gray_resizer: None,
+                ..self
+            }
+        }
+    }
+
+    /// Constructs a hasher converting to grayscale and resizing with implementations owned by this crate.
+    ///
+    /// See `AverageHash::with_stable_pipeline` for the steps it covers.
+    pub fn with_stable_pipeline(self, stable_pipeline: bool) -> Self {
+        let ((grayscale, gray_resizer), replaced_steps) = stable_pipeline_steps(
+            stable_pipeline,
+            (self.preprocess.grayscale, self.gray_resizer),
+            self.replaced_steps,
+        );
+        PerceptualHash {
+            preprocess: Preprocess {
+                grayscale,
+                ..self.preprocess
+            },
+            gray_resizer,
+            replaced_steps,
+            ..self
+        }
+    }
+
+    /// Constructs a hasher rotating JPEG files upright by their EXIF orientation in `hash_path`.
+    ///
+    /// The default is `true`. Images passed to `hash` are never rotated.
+    pub fn with_exif_orientation(self, exif_orientation: bool) -> Self {
+        PerceptualHash {
+            exif_orientation,
+            ..self
+        }
+    }
+
+    /// Constructs a hasher rejecting source images narrower or shorter than `min_source` in `try_hash`.
+    ///
+    /// The default is 0, which allows any source images to be upscaled to the image size.
+    pub fn with_min_source(self, min_source: usize) -> Self {
+        PerceptualHash { min_source, ..self }
+    }
+
+    preprocess_builders!();
+
+    /// Constructs a hasher with the transform applied to each row.
+    pub fn with_transform(self, transform: TransformKind) -> Self {
+        PerceptualHash { transform, ..self }
+    }
+
+    /// Constructs a hasher selecting coefficients of the 2D transform in zig-zag order.
+    ///
+    /// When enabled, the transform is applied to both rows and columns, and the first
+    /// `hash_width * hash_height` coefficients after the DC term are selected in the
+    /// JPEG-style zig-zag order instead of the top-left block.
+    pub fn with_zigzag(self, zigzag: bool) -> Self {
+        PerceptualHash { zigzag, ..self }
+    }
+
+    /// Constructs a hasher applying the transform to each column of the transformed rows as well.
+    ///
+    /// The coefficients of the resulting 2D transform are then selected like those of the rows.
+    /// It has no further effect with `with_zigzag`, which always transforms the columns.
+    pub fn with_column_transform(self, column_transform: bool) -> Self {
+        PerceptualHash {
+            column_transform,
+            ..self
+        }
+    }
+
+    /// Constructs a hasher with the pivot that the selected coefficients are thresholded against.
+    pub fn with_pivot(self, pivot: Pivot) -> Self {
+        PerceptualHash { pivot, ..self }
+    }
+
+    /// Constructs a hasher excluding the `drop_low` lowest-frequency coefficients before selecting the hash block.
+    ///
+    /// The coefficients are dropped from the start of each row, or from the start of the zig-zag order when
+    /// `with_zigzag` is enabled. The default is 1, which excludes the DC term.
+    pub fn with_drop_low(self, drop_low: usize) -> Self {
+        PerceptualHash { drop_low, ..self }
+    }
+
+    /// Constructs a hasher excluding only the DC coefficient at row 0 and column 0 from the hash block.
+    ///
+    /// By default, `with_drop_low` drops the first column of every row, including the lowest
+    /// frequency of each row other than row 0. In this mode, the block starts at column 0 and only
+    /// its first coefficient is excluded, so the hash has `hash_width * hash_height - 1` bits.
+    /// `with_drop_low` is ignored in this mode, and it has no effect with `with_zigzag`, whose
+    /// default `drop_low` of 1 already excludes only the DC coefficient.
+    pub fn with_exclude_dc_only(self, exclude_dc_only: bool) -> Self {
+        PerceptualHash {
+            exclude_dc_only,
+            ..self
+        }
+    }
+
+    /// Constructs a hasher calculating the hash of each RGB channel instead of the grayscale image.
+    ///
+    /// The hashes of the red, green and blue channels are concatenated in this order, so the hash is
+    /// three times longer. This distinguishes images that differ only in their hues.
+    pub fn with_color(self, color: bool) -> Self {
+        PerceptualHash { color, ..self }
+    }
+
+    /// Constructs a hasher voting over `n` hashes of the image resized at jittered offsets.
+    ///
+    /// The first hash is of the preprocessed image as is, and the others of it cropped by up to one
+    /// resized pixel from its top-left corner, with the offsets evenly spaced along the diagonal. Each
+    /// bit of the hash is set if it is set in more than half of the hashes. This stabilizes borderline
+    /// bits against small pixel differences such as those between JPEG decoders, at `n` times the cost
+    /// of `hash` and `try_hash`. Other methods use the unjittered image only, as with `n` of 1, and `n`
+    /// of 0 or 1 disables the jitter.
+    pub fn with_oversample(self, n: usize) -> Self {
+        PerceptualHash {
+            oversample: n,
+            ..self
+        }
+    }
+
+    /// Calculates the hash of the image file, decoding it reduced to the image size where possible.
+    ///
+    /// The file is decoded by `open_jpeg_scaled` within `default_limits`, or at full resolution like
+    /// `open_image` if a crop or a region detector is set, since their coordinates refer to the full image.
+    /// Only JPEG files are decoded reduced, with the `jpeg` feature; other formats such as TIFF are
+    /// decoded at full resolution. A reduced JPEG may hash slightly differently from `hash` of the
+    /// same file opened with `open_image`.
+    /// With `ColorSpace::Cmyk` and the `jpeg` feature, CMYK JPEG files are decoded to their ink values
+    /// and converted by the formula of `GrayscaleImage::from_cmyk`.
+    /// JPEG files are then rotated upright by their EXIF orientation unless disabled by
+    /// `with_exif_orientation`, and hashed by `try_hash`, whose errors are returned.
+    pub fn hash_path<Q: AsRef<std::path::Path>>(&self, path: Q) -> Result<Hash, HashError> {
+        let mut image = self.preprocess.open(&path, self.image_size, false)?;
+        if self.exif_orientation {
+            image = orient(image, &path);
+        }
+        self.try_hash(&image)
+    }
+
+    /// Calculates perceptual hash (pHash) of the image and returns as a hex string.
+    ///
+    /// If the resizer returns an image whose size is not the image size, it is resampled to the
+    /// image size by nearest neighbor. Use `try_hash` to reject such images instead.
+    pub fn hash(&self, image: &image::DynamicImage) -> Hash {
+        let hashes: Vec<Hash> = (0..self.oversample.max(1))
+            .map(|jitter| {
+                let channels = self.fitted_channels(image, jitter);
+                let (values, pivot) = self.channel_values(&channels);
+                threshold(&values, pivot)
+            })
+            .collect();
+        majority_hash(&hashes).unwrap()
+    }
+
+    /// Calculates perceptual hash (pHash) of the image, rejecting images that cannot be meaningfully hashed.
+    ///
+    /// Returns `HashError::SourceTooSmall` if the image is smaller than the minimum source size,
+    /// `HashError::UnexpectedSize` if the resizer returns an image whose size is not the image size,
+    /// `HashError::WeightMismatch` if the weight matrix does not match the selected coefficients,
+    /// `HashError::LowContrast` if the preprocessed image is nearly constant, or `HashError::NonFinite`
+    /// if a NaN or infinite value reaches the threshold.
+    pub fn try_hash(&self, image: &image::DynamicImage) -> Result<Hash, HashError> {
+        check_source(image, self.min_source)?;
+        let mut hashes = Vec::new();
+        for jitter in 0..self.oversample.max(1) {
+            let channels = self.preprocess_channels(image, jitter);
+            for channel in &channels {
+                channel.check_size(self.image_size)?;
+            }
+            let (values, pivot) = self.try_channel_values(&channels)?;
+            if channels.iter().all(|c| c.check_contrast().is_err()) {
+                return Err(HashError::LowContrast);
+            }
+            hashes.push(checked_threshold(&values, pivot)?);
+        }
+        majority_hash(&hashes)
+    }
+
+    /// Calculates perceptual hash (pHash) of the image along with a confidence score.
+    ///
+    /// The score is the mean distance of the thresholded values from the threshold relative to
+    /// their range. Scores near 0.0 indicate flat or ambiguous images whose bits are unreliable.
+    /// Images of another size from the resizer are resampled as in `hash`.
+    pub fn hash_with_confidence(&self, image: &image::DynamicImage) -> (Hash, f64) {
+        let channels = self.fitted_channels(image, 0);
+        let (values, pivot) = self.channel_values(&channels);
+        (threshold(&values, pivot), confidence(&values, pivot))
+    }
+
+    /// Calculates the values thresholded in perceptual hash (pHash) of the image, relative to the threshold.
+    ///
+    /// These are the selected low-frequency coefficients minus their mean or median. The bits of
+    /// `hash` are set where the values are positive. Images of another size from the resizer are
+    /// resampled as in `hash`.
+    pub fn features(&self, image: &image::DynamicImage) -> Vec<f64> {
+        let channels = self.fitted_channels(image, 0);
+        let (values, pivot) = self.channel_values(&channels);
+        values.iter().map(|v| v - pivot).collect()
+    }
+
+    /// Calculates perceptual hash (pHash) of the grayscale image without preprocessing or resizing, rejecting
+    /// images that cannot be meaningfully hashed.
+    ///
+    /// Returns `HashError::WeightMismatch` if the weight matrix does not match the selected coefficients,
+    /// `HashError::LowContrast` if the image is nearly constant, or `HashError::NonFinite` if the image
+    /// has NaN or infinite pixels.
+    pub fn try_hash_gray<P: Into<f64> + Copy>(
+        &self,
+        image: &GrayscaleImage<P>,
+    ) -> Result<Hash, HashError> {
+        let (values, pivot) = try_perceptual_hash_values(image, self)?;
+        image.check_contrast()?;
+        checked_threshold(&values, pivot)
+    }
+
+    /// Calculates perceptual hash (pHash) of the grayscale image without preprocessing or resizing.
+    pub fn hash_gray<P: Into<f64> + Copy>(&self, image: &GrayscaleImage<P>) -> Hash {
+        perceptual_hash_core(image, self)
+    }
+
+    /// Returns the number of columns skipped at the start of each row in the block selection.
+    fn skip(&self) -> usize {
+        if self.exclude_dc_only {
+            0
+        } else {
+            self.drop_low
+        }
+    }
+
+    /// Returns the preprocessed RGB channels if `color` is enabled, or the preprocessed grayscale image,
+    /// resized at the `jitter`-th of the `oversample` offsets.
+    fn preprocess_channels(
+        &self,
+        image: &image::DynamicImage,
+        jitter: usize,
+    ) -> Vec<GrayscaleImage<f32>> {
+        if !self.color {
+            return vec![self.preprocess(image, jitter)];
+        }
+        let rgb = image.to_rgb8();
+        (0..3)
+            .map(|c| {
+                let channel = image::GrayImage::from_fn(rgb.width(), rgb.height(), |x, y| {
+                    image::Luma([rgb.get_pixel(x, y)[c]])
+                });
+                self.preprocess(&image::DynamicImage::ImageLuma8(channel), jitter)
+            })
+            .collect()
+    }
+
+    /// Returns the preprocessed channels resampled to the image size if the resizer returned
+    /// another size.
+    fn fitted_channels(
+        &self,
+        image: &image::DynamicImage,
+        jitter: usize,
+    ) -> Vec<GrayscaleImage<f32>> {
+        self.preprocess_channels(image, jitter)
+            .into_iter()
+            .map(|channel| channel.fit(self.image_size))
+            .collect()
+    }
+
+    /// Returns the values of the channels relative to their own pivots, and the pivot 0.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the weight matrix does not match the selected coefficients.
+    fn channel_values(&self, channels: &[GrayscaleImage<f32>]) -> (Vec<f64>, f64) {
+        self.try_channel_values(channels)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Returns the values of the channels relative to their own pivots, and the pivot 0, or
+    /// `HashError::WeightMismatch` if the weight matrix does not match the selected coefficients.
+    fn try_channel_values(
+        &self,
+        channels: &[GrayscaleImage<f32>],
+    ) -> Result<(Vec<f64>, f64), HashError> {
+        if let [image] = channels {
+            return try_perceptual_hash_values(image, self);
+        }
+        let mut values = Vec::new();
+        for channel in channels {
+            let (v, pivot) = try_perceptual_hash_values(channel, self)?;
+            values.extend(v.iter().map(|x| x - pivot));
+        }
+        Ok((values, 0.0))
+    }
+
+    fn preprocess(&self, image: &image::DynamicImage, jitter: usize) -> GrayscaleImage<f32> {
+        let image = self.preprocess.apply(image);
+        let (width, height) = self.image_size;
+        let image = if self.oversample > 1 {
+            jittered(&image, width, height, jitter, self.oversample)
+        } else {
+            image
+        };
+        let image = match (self.gray_resizer, self.resize_filter) {
+            (Some(gray_resizer), _) => gray_resizer(&image.into_luma8(), width, height).into(),
+            (None, Some(filter)) => image
+                .resize_exact(width as u32, height as u32, filter)
+                .into(),
+            (None, None) => (self.resizer)(&image, width, height).into(),
+        };
+        self.preprocess.finish(image)
+    }
+}
+
+impl ImageHasher for PerceptualHash {
+    fn hash(&self, image: &image::DynamicImage) -> Hash {
+        PerceptualHash::hash(self, image)
+    }
+
+    fn hash_gray(&self, image: &GrayscaleImage) -> Hash {
+        PerceptualHash::hash_gray(self, image)
+    }
+}
+
+impl Default for PerceptualHash {
+    /// Creates a new `PerceptualHasher` with default parameters.
+    fn default() -> Self {
+        PerceptualHash {
+            image_size: (32, 32),
+            hash_size: (8, 8),
+            resizer: resize,
+            gray_resizer: None,
+            replaced_steps: None,
+            preprocess: Preprocess::default(),
+            transform: TransformKind::default(),
+            zigzag: false,
+            column_transform: false,
+            pivot: Pivot::default(),
+            drop_low: 1,
+            resize_filter: None,
+            transpose_output: false,
+            zero_pivot: false,
+            exclude_dc_only: false,
+            dct_length: None,
+            top_k: None,
+            weights: None,
+            color: false,
+            min_source: 0,
+            exif_orientation: true,
+            oversample: 1,
+        }
+    }
+}
+
+/// Crops the image by the `jitter`-th of `n` offsets evenly spaced up to one pixel of the image resized to `width` x `height`.
+///
+/// The 0th jitter returns the image as is, and the other crops have the same size. Images not larger
+/// than the resized size are returned as is.
+fn jittered(
+    image: &image::DynamicImage,
+    width: usize,
+    height: usize,
+    jitter: usize,
+    n: usize,
+) -> image::DynamicImage {
+    let (w, h) = (image.width() as usize, image.height() as usize);
+    let (step_x, step_y) = (w / width.max(1), h / height.max(1));
+    if jitter == 0 || step_x == 0 || step_y == 0 || w <= step_x || h <= step_y {
+        return image.clone();
+    }
+    let x = jitter * step_x / n;
+    let y = jitter * step_y / n;
+    image.crop_imm(x as u32, y as u32, (w - step_x) as u32, (h - step_y) as u32)
+}
+
+/// Calculates perceptual hash (pHash) of the image.
+pub fn perceptual_hash(image: &image::DynamicImage) -> Hash {
+    let image: GrayscaleImage = resize(&image.grayscale(), 32, 32).into();
+    perceptual_hash_core(&image, &PerceptualHash::default())
+}
+
+/// Calculates perceptual hash (pHash) of the image file.
+///
+/// The image format must be enabled in the features of the `image` crate. The image is decoded within
+/// `default_limits`, and `HashError::TooLarge` is returned if it exceeds them. JPEG files are rotated
+/// upright by their EXIF orientation.
+pub fn perceptual_hash_path<P: AsRef<std::path::Path>>(path: P) -> Result<Hash, HashError> {
+    let image = open_image(&path, default_limits())?;
+    Ok(perceptual_hash(&orient(image, &path)))
+}
+
+fn perceptual_hash_core<P: Into<f64> + Copy>(
+    image: &GrayscaleImage<P>,
+    hasher: &PerceptualHash,
+) -> Hash {
+    let (values, pivot) = perceptual_hash_values(image, hasher);
+    threshold(&values, pivot)
+}
+
+/// Returns the values thresholded in perceptual hash (pHash) and their pivot.
+///
+/// # Panics
+///
+/// Panics if the weight matrix does not match the selected coefficients.
+fn perceptual_hash_values<P: Into<f64> + Copy>(
+    image: &GrayscaleImage<P>,
+    hasher: &PerceptualHash,
+) -> (Vec<f64>, f64) {
+    try_perceptual_hash_values(image, hasher).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Returns the values thresholded in perceptual hash (pHash) and their pivot, or
+/// `HashError::WeightMismatch` if the weight matrix does not match the selected coefficients.
+///
+/// The weights are checked against the coefficients actually selected, so the check cannot drift
+/// from the selection rules.
+fn try_perceptual_hash_values<P: Into<f64> + Copy>(
+    image: &GrayscaleImage<P>,
+    hasher: &PerceptualHash,
+) -> Result<(Vec<f64>, f64), HashError> {
+    let (hash_width, hash_height) = hasher.hash_size;
+    let width = hasher.dct_length.unwrap_or(image.width);
+    let mean = if hasher.zero_pivot {
+        image.iter_rows().flatten().sum::<f64>() / (image.width * image.height) as f64
+    } else {
+        0.0
+    };
+    let mut dct_rows = vec![0.0; width * image.height];
+    for (y, row) in image.iter_rows().enumerate() {
+        let mut row: Vec<f64> = row.take(width).map(|v| v - mean).collect();
+        row.resize(width, 0.0);
+        let dct = hasher.transform.apply(&row);
+        for (x, v) in dct.iter().enumerate() {
+            dct_rows[y * width + x] = *v;
+        }
+    }
+    if hasher.column_transform {
+        dct_rows = transform_columns(&dct_rows, width, image.height, hasher.transform);
+    }
+    if let Some(k) = hasher.top_k {
+        let mut candidates = select_block(&dct_rows, width, hasher.skip(), width, image.height);
+        if hasher.exclude_dc_only && !candidates.is_empty() {
+            candidates.remove(0);
+        }
+        return Ok((top_k_values(&candidates, k), 0.0));
+    }
+    let mut low_freqs: Vec<f64> = if hasher.zigzag {
+        let dct = if hasher.column_transform {
+            std::mem::take(&mut dct_rows)
+        } else {
+            transform_columns(&dct_rows, width, image.height, hasher.transform)
+        };
+        zigzag_indices(width, image.height)
+            .into_iter()
+            .skip(hasher.drop_low)
+            .take(hash_width * hash_height)
+            .map(|(x, y)| dct[y * width + x])
+            .collect()
+    } else {
+        let block = select_block(&dct_rows, width, hasher.skip(), hash_width, hash_height);
+        let mut block = if hasher.transpose_output {
+            let columns = hash_width.min(width.saturating_sub(hasher.skip()));
+            transpose(&block, columns)
+        } else {
+            block
+        };
+        if hasher.exclude_dc_only && !block.is_empty() {
+            block.remove(0);
+        }
+        block
+    };
+    if let Some(weights) = &hasher.weights {
+        if weights.len() != low_freqs.len() {
+            return Err(HashError::WeightMismatch(low_freqs.len(), weights.len()));
+        }
+        low_freqs = low_freqs.iter().zip(weights).map(|(v, w)| v * w).collect();
+    }
+    let pivot = match hasher.pivot {
+        _ if hasher.zero_pivot => 0.0,
+        Pivot::Mean => low_freqs.iter().sum::<f64>() / low_freqs.len() as f64,
+        Pivot::Median => median(&low_freqs),
+    };
+    Ok((low_freqs, pivot))
+}
+
+/// Selects the `hash_width` x `hash_height` block of the row-major values, excluding the first `skip` columns.
+fn select_block(
+    values: &[f64],
+    width: usize,
+    skip: usize,
+    hash_width: usize,
+    hash_height: usize,
+) -> Vec<f64> {
+    values
+        .chunks(width)
+        .take(hash_height)
+        .flat_map(|row| row.iter().skip(skip).take(hash_width).copied())
+        .collect()
+}
+
+/// Returns values whose signs encode the top `k` values by magnitude and their signs.
+///
+/// The first half is positive for the top `k` values and negative otherwise, and the second half is
+/// the value itself for the top `k` values and zero otherwise. Ties resolve to the lower index.
+fn top_k_values(values: &[f64], k: usize) -> Vec<f64> {
+    let mut order: Vec<usize> = (0..values.len()).collect();
+    order.sort_by(|&a, &b| {
+        values[b]
+            .abs()
+            .partial_cmp(&values[a].abs())
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(a.cmp(&b))
+    });
+    let mut result = vec![-1.0; values.len()];
+    result.resize(2 * values.len(), 0.0);
+    for &i in order.iter().take(k) {
+        result[i] = 1.0;
+        result[values.len() + i] = values[i];
+    }
+    result
+}
+
+/// Transposes the row-major values with the number of columns.
+fn transpose(values: &[f64], columns: usize) -> Vec<f64> {
+    if columns == 0 {
+        return values.to_vec();
+    }
+    let rows = values.len() / columns;
+    (0..columns)
+        .flat_map(|x| (0..rows).map(move |y| values[y * columns + x]))
+        .collect()
+}
+
+/// Represents the pivot that values are thresholded against.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Pivot {
+    /// The mean of the values. This is the default.
+    #[default]
+    Mean,
+    /// The median of the values.
+    Median,
+}
+
+/// Sets the bits of the values above the pivot.
+fn threshold(values: &[f64], pivot: f64) -> Hash {
+    values.iter().map(|&v| v > pivot).collect()
+}
+
+/// Thresholds the values like `threshold`, returning `HashError::NonFinite` if a value or the pivot is NaN or infinite.
+fn checked_threshold(values: &[f64], pivot: f64) -> Result<Hash, HashError> {
+    if !pivot.is_finite() || values.iter().any(|v| !v.is_finite()) {
+        return Err(HashError::NonFinite);
+    }
+    Ok(threshold(values, pivot))
+}
+
+/// Returns the mean distance of the values from the pivot relative to half the range of the values.
+///
+/// The score is 0.0 for constant values and 1.0 when the values sit at the ends of a range centered on the pivot.
+fn confidence(values: &[f64], pivot: f64) -> f64 {
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    if values.is_empty() || max <= min {
+        return 0.0;
+    }
+    let spread = values.iter().map(|v| (v - pivot).abs()).sum::<f64>() / values.len() as f64;
+    2.0 * spread / (max - min)
+}
+
+fn median(values: &[f64]) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let n = sorted.len();
+    if n == 0 {
+        0.0
+    } else if n % 2 == 0 {
+        (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0
+    } else {
+        sorted[n / 2]
+    }
+}
+
+/// Returns the median of the values, counting them in 256 bins if they are all 8-bit levels.
+///
+/// This is equal to `median` but linear in the number of values for 8-bit pixels.
+fn level_median(values: &[f64]) -> f64 {
+    let mut counts = [0usize; 256];
+    for &v in values {
+        if !(0.0..=255.0).contains(&v) || v.fract() != 0.0 {
+            return median(values);
+        }
+        counts[v as usize] += 1;
+    }
+    let n = values.len();
+    if n == 0 {
+        return 0.0;
+    }
+    let nth = |rank: usize| {
+        let mut seen = 0;
+        for (level, &count) in counts.iter().enumerate() {
+            seen += count;
+            if seen > rank {
+                return level as f64;
+            }
+        }
+        255.0
+    };
+    if n % 2 == 0 {
+        (nth(n / 2 - 1) + nth(n / 2)) / 2.0
+    } else {
+        nth(n / 2)
+    }
+}
+
+/// Applies the transform to each column of the row-major values.
+fn transform_columns(
+    values: &[f64],
+    width: usize,
+    height: usize,
+    transform: TransformKind,
+) -> Vec<f64> {
+    let mut result = vec![0.0; width * height];
+    for x in 0..width {
+        let column: Vec<f64> = (0..height).map(|y| values[y * width + x]).collect();
+        for (y, v) in transform.apply(&column).iter().enumerate() {
+            result[y * width + x] = *v;
+        }
+    }
+    result
+}
+
+/// Returns the `(x, y)` positions of the grid in JPEG-style zig-zag order.
+fn zigzag_indices(width: usize, height: usize) -> Vec<(usize, usize)> {
+    if width == 0 || height == 0 {
+        return Vec::new();
+    }
+    let mut indices = Vec::with_capacity(width * height);
+    for s in 0..(width + height).saturating_sub(1) {
+        let min_y = s.saturating_sub(width - 1);
+        let max_y = s.min(height - 1);
+        if s % 2 == 0 {
+            indices.extend((min_y..=max_y).rev().map(|y| (s - y, y)));
+        } else {
+            indices.extend((min_y..=max_y).map(|y| (s - y, y)));
+        }
+    }
+    indices
+}
+
+/// Represents a transform applied to each row in perceptual hash (pHash) calculation.
+///
+/// Variants may be added by features, so matches should have a wildcard arm.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TransformKind {
+    /// Type-II discrete cosine transform (DCT-II). This is the default.
+    #[default]
+    Dct2,
+    /// Type-II discrete cosine transform (DCT-II) computed in `f32`.
+    ///
+    /// This uses a single-precision cosine table, halving the memory traffic of `Dct2`, and
+    /// produces the same bits as `Dct2` for typical images.
+    Dct2F32,
+    /// Type-IV discrete cosine transform (DCT-IV).
+    ///
+    /// The DCT-IV has no DC coefficient, so the mean of each row leaks into every coefficient with
+    /// alternating signs. For typical images, whose rows have large means, the hash degenerates to
+    /// alternating bits such as `5555555555555555`, so this is only useful for zero-mean inputs.
+    Dct4,
+    /// Type-II discrete sine transform (DST-II).
+    Dst2,
+    /// Fixed-point approximation of the type-II discrete cosine transform (DCT-II).
+    ///
+    /// This uses integer arithmetic with `FIXED_DCT_BITS` fractional bits and Lee's recursive
+    /// factorization, which takes `O(n log n)` operations for power-of-two image sizes instead of
+    /// the `O(n^2)` of `Dct2`, and produces the same bits as `Dct2` for typical images.
+    #[cfg(feature = "fixed-dct")]
+    FixedDct2,
+}
+
+impl TransformKind {
+    fn apply(&self, input: &[f64]) -> Vec<f64> {
+        match self {
+            TransformKind::Dct2 => dct2(input),
+            TransformKind::Dct2F32 => dct2_f32(input),
+            TransformKind::Dct4 => dct4(input),
+            TransformKind::Dst2 => dst2(input),
+            #[cfg(feature = "fixed-dct")]
+            TransformKind::FixedDct2 => fixed_dct2(input),
+        }
+    }
+}
+
+fn dct2(input: &[f64]) -> Vec<f64> {
     // scipy-style dct-ii
+    // the terms are summed in ascending order with compensation, so borderline coefficients do not
+    // depend on the accumulated rounding error
+    let n = input.len();
+    (0..n)
+        .map(|k| {
+            compensated_sum(input.iter().enumerate().map(|(i, xi)| {
+                2.0_f64
+                    * xi
+                    * (std::f64::consts::PI * k as f64 * (2 * i + 1) as f64 / (2 * n) as f64).cos()
+            }))
+        })
+        .collect()
+}
+
+fn dct2_f32(input: &[f64]) -> Vec<f64> {
+    // scipy-style dct-ii in single precision
+    // cos(pi * k * (2i + 1) / 2n) only depends on k * (2i + 1) modulo 4n
+    let n = input.len();
+    let period = 4 * n;
+    let table: Vec<f32> = (0..period)
+        .map(|m| (std::f64::consts::PI * m as f64 / (2 * n) as f64).cos() as f32)
+        .collect();
+    let input: Vec<f32> = input.iter().map(|&x| x as f32).collect();
+    (0..n)
+        .map(|k| {
+            let step = 2 * k % period;
+            let mut m = k % period;
+            let mut sum = 0.0_f32;
+            for xi in &input {
+                sum += xi * table[m];
+                m += step;
+                if m >= period {
+                    m -= period;
+                }
+            }
+            f64::from(2.0 * sum)
+        })
+        .collect()
+}
+
+/// Sums the values in order with the Kahan-Babuska-Neumaier compensated summation.
+fn compensated_sum(values: impl Iterator<Item = f64>) -> f64 {
+    let mut sum = 0.0_f64;
+    let mut compensation = 0.0_f64;
+    for v in values {
+        let t = sum + v;
+        if sum.abs() >= v.abs() {
+            compensation += (sum - t) + v;
+        } else {
+            compensation += (v - t) + sum;
+        }
+        sum = t;
+    }
+    sum + compensation
+}
+
+/// The number of fractional bits of the fixed-point values in `fixed_dct2`.
+#[cfg(feature = "fixed-dct")]
+const FIXED_DCT_BITS: u32 = 20;
+
+#[cfg(feature = "fixed-dct")]
+fn fixed_dct2(input: &[f64]) -> Vec<f64> {
+    // scipy-style dct-ii with the inputs rounded to integers
+    let input: Vec<i64> = input
+        .iter()
+        .map(|x| (x.round() as i64) << FIXED_DCT_BITS)
+        .collect();
+    fixed_dct2_q(&input)
+        .iter()
+        .map(|x| (2 * x) as f64 / f64::from(1 << FIXED_DCT_BITS))
+        .collect()
+}
+
+/// Multiplies two fixed-point values with `FIXED_DCT_BITS` fractional bits, rounding to nearest.
+#[cfg(feature = "fixed-dct")]
+fn fixed_mul(a: i64, b: i64) -> i64 {
+    ((i128::from(a) * i128::from(b) + (1 << (FIXED_DCT_BITS - 1))) >> FIXED_DCT_BITS) as i64
+}
+
+/// Computes the unnormalized DCT-II `X[k] = sum(x[i] * cos(pi * k * (2i + 1) / 2n))` of
+/// fixed-point values.
+///
+/// Even lengths use Lee's recursive factorization, which splits the transform into two of half
+/// the length, so power-of-two lengths take `O(n log n)` operations. Odd lengths fall back to the
+/// direct sum.
+#[cfg(feature = "fixed-dct")]
+fn fixed_dct2_q(input: &[i64]) -> Vec<i64> {
+    let n = input.len();
+    if n % 2 == 1 {
+        // cos(pi * k * (2i + 1) / 2n) only depends on k * (2i + 1) modulo 4n
+        let period = 4 * n;
+        let table: Vec<i64> = (0..period)
+            .map(|m| {
+                ((std::f64::consts::PI * m as f64 / (2 * n) as f64).cos()
+                    * f64::from(1 << FIXED_DCT_BITS))
+                .round() as i64
+            })
+            .collect();
+        return (0..n)
+            .map(|k| {
+                let step = 2 * k % period;
+                let mut m = k % period;
+                let mut sum = 0i64;
+                for xi in input {
+                    sum += fixed_mul(*xi, table[m]);
+                    m += step;
+                    if m >= period {
+                        m -= period;
+                    }
+                }
+                sum
+            })
+            .collect();
+    }
+    if n == 0 {
+        return Vec::new();
+    }
+    // the even coefficients are the dct of the folded sums, and the odd ones are adjacent pairs
+    // of the dct of the folded differences scaled by 1 / 2cos(pi * (2i + 1) / 2n)
+    let half = n / 2;
+    let mut sums = Vec::with_capacity(half);
+    let mut differences = Vec::with_capacity(half);
+    for i in 0..half {
+        let (x, y) = (input[i], input[n - 1 - i]);
+        let scale = (f64::from(1 << FIXED_DCT_BITS)
+            / (2.0 * (std::f64::consts::PI * (2 * i + 1) as f64 / (2 * n) as f64).cos()))
+        .round() as i64;
+        sums.push(x + y);
+        differences.push(fixed_mul(x - y, scale));
+    }
+    let even = fixed_dct2_q(&sums);
+    let odd = fixed_dct2_q(&differences);
+    let mut result = Vec::with_capacity(n);
+    for k in 0..half {
+        result.push(even[k]);
+        result.push(odd[k] + odd.get(k + 1).copied().unwrap_or(0));
+    }
+    result
+}
+
+fn dct4(input: &[f64]) -> Vec<f64> {
+    // scipy-style dct-iv
     let n = input.len();
     (0..n)
         .map(|k| {
@@ -386,7 +4671,8 @@ fn dct2(input: &[f64]) -> Vec<f64> {
                 .map(|(i, xi)| {
                     2.0_f64
                         * xi
-                        * (std::f64::consts::PI * k as f64 * (2 * i + 1) as f64 / (2 * n) as f64)
+                        * (std::f64::consts::PI * (2 * k + 1) as f64 * (2 * i + 1) as f64
+                            / (4 * n) as f64)
                             .cos()
                 })
                 .sum::<f64>()
@@ -394,6 +4680,155 @@ fn dct2(input: &[f64]) -> Vec<f64> {
         .collect()
 }
 
+fn dst2(input: &[f64]) -> Vec<f64> {
+    // scipy-style dst-ii
+    let n = input.len();
+    (0..n)
+        .map(|k| {
+            input
+                .iter()
+                .enumerate()
+                .map(|(i, xi)| {
+                    2.0_f64
+                        * xi
+                        * (std::f64::consts::PI * (k + 1) as f64 * (2 * i + 1) as f64
+                            / (2 * n) as f64)
+                            .sin()
+                })
+                .sum::<f64>()
+        })
+        .collect()
+}
+
+#[test]
+fn test_equalize_histogram() {
+    let image = image::GrayImage::from_raw(4, 1, vec![100, 100, 110, 120]).unwrap();
+    assert_eq!(equalize_histogram(image).into_raw(), vec![0, 0, 128, 255]);
+    let flat = image::GrayImage::from_raw(2, 1, vec![50, 50]).unwrap();
+    assert_eq!(equalize_histogram(flat).into_raw(), vec![50, 50]);
+}
+
+#[test]
+fn test_sobel() {
+    let image = image::GrayImage::from_fn(4, 3, |x, _| image::Luma([if x < 2 { 0 } else { 100 }]));
+    let edges = sobel(&image);
+    for y in 0..3 {
+        let row: Vec<u8> = (0..4).map(|x| edges.get_pixel(x, y)[0]).collect();
+        assert_eq!(row, vec![0, 255, 255, 0]);
+    }
+    let flat = image::GrayImage::from_pixel(3, 3, image::Luma([80]));
+    assert!(sobel(&flat).pixels().all(|p| p[0] == 0));
+}
+
+#[test]
+fn test_standardize() {
+    let image = image::GrayImage::from_raw(4, 1, vec![10, 20, 30, 40]).unwrap();
+    let brighter = image::GrayImage::from_raw(4, 1, vec![110, 130, 150, 170]).unwrap();
+    let expected = vec![85, 114, 142, 171];
+    assert_eq!(standardize(image).into_raw(), expected);
+    assert_eq!(standardize(brighter).into_raw(), expected);
+    let flat = image::GrayImage::from_raw(2, 1, vec![50, 50]).unwrap();
+    assert_eq!(standardize(flat).into_raw(), vec![128, 128]);
+}
+
+#[test]
+fn test_center_square() {
+    assert_eq!(center_square(30, 10), (10, 0, 10));
+    assert_eq!(center_square(10, 31), (0, 10, 10));
+    assert_eq!(center_square(8, 8), (0, 0, 8));
+}
+
+#[test]
+fn test_zigzag_indices() {
+    let actual: Vec<usize> = zigzag_indices(8, 8)
+        .iter()
+        .map(|(x, y)| y * 8 + x)
+        .collect();
+    let expected = vec![
+        0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27,
+        20, 13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51,
+        58, 59, 52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+    ];
+    assert_eq!(actual, expected);
+    assert_eq!(
+        zigzag_indices(3, 2),
+        vec![(0, 0), (1, 0), (0, 1), (1, 1), (2, 0), (2, 1)]
+    );
+    assert!(zigzag_indices(0, 4).is_empty());
+    assert!(zigzag_indices(4, 0).is_empty());
+}
+
+#[test]
+fn test_select_block() {
+    let values: Vec<f64> = (0..32).map(f64::from).collect();
+    assert_eq!(
+        select_block(&values, 8, 1, 3, 2),
+        vec![1.0, 2.0, 3.0, 9.0, 10.0, 11.0]
+    );
+    assert_eq!(
+        select_block(&values, 8, 1, 2, 3),
+        vec![1.0, 2.0, 9.0, 10.0, 17.0, 18.0]
+    );
+    assert_eq!(
+        select_block(&values, 8, 3, 2, 2),
+        vec![3.0, 4.0, 11.0, 12.0]
+    );
+}
+
+#[test]
+fn test_confidence() {
+    assert_eq!(confidence(&[1.0, 1.0], 1.0), 0.0);
+    assert_eq!(confidence(&[0.0, 10.0], 5.0), 1.0);
+    assert!((confidence(&[0.0, 1.0, 2.0, 3.0], 1.5) - 2.0 / 3.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_transpose() {
+    let values = vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+    assert_eq!(transpose(&values, 3), vec![1.0, 4.0, 2.0, 5.0, 3.0, 6.0]);
+    assert_eq!(transpose(&values, 2), vec![1.0, 3.0, 5.0, 2.0, 4.0, 6.0]);
+}
+
+#[test]
+fn test_local_deviations() {
+    let image = GrayscaleImage::new(vec![0, 3, 6, 9], 4, 1);
+    assert_eq!(local_deviations(&image, 3), vec![-1.5, 0.0, 0.0, 1.5]);
+    assert_eq!(local_deviations(&image, 1), vec![0.0; 4]);
+}
+
+#[test]
+fn test_median() {
+    assert_eq!(median(&[3.0, 1.0, 2.0]), 2.0);
+    assert_eq!(median(&[4.0, 1.0, 3.0, 2.0]), 2.5);
+}
+
+#[test]
+fn test_level_median() {
+    let levels: Vec<f64> = (0..101).map(|i| f64::from((i * 37 + 5) % 256)).collect();
+    for n in [0, 1, 2, 3, 64, 101] {
+        assert_eq!(level_median(&levels[..n]), median(&levels[..n]));
+    }
+    assert_eq!(level_median(&[7.0, 7.0, 200.0, 7.0]), 7.0);
+    assert_eq!(level_median(&[1.5, 0.5, 300.0]), 1.5);
+    assert_eq!(level_median(&[-1.0, 4.0]), 1.5);
+}
+
+#[test]
+fn test_compensated_sum() {
+    let values = [1e16, 1.0, -1e16, 1.0];
+    assert_eq!(values.iter().sum::<f64>(), 1.0);
+    assert_eq!(compensated_sum(values.iter().copied()), 2.0);
+    assert_eq!(compensated_sum(std::iter::empty()), 0.0);
+}
+
+#[test]
+fn test_dct2_cancellation() {
+    // the DC coefficient sits exactly on the threshold of 4.0 and naive summation misses it
+    let input = [1e16, 1.0, -1e16, 1.0];
+    assert_eq!(dct2(&input)[0], 4.0);
+    assert!(threshold(&dct2(&[1e16, 1.5, -1e16, 1.0])[..1], 4.0).bits[0]);
+}
+
 #[test]
 fn test_dct2() {
     let input = vec![0., 1., 2.];
@@ -404,3 +4839,252 @@ fn test_dct2() {
         assert!((a - e).abs() < 1e-8);
     }
 }
+
+#[test]
+fn test_dct4() {
+    let input = vec![0., 1., 2.];
+    let actual = dct4(&input);
+    let expected = [2.44948974e+00, -4.24264069e+00, 2.44948974e+00];
+    assert_eq!(actual.len(), expected.len());
+    for (a, e) in actual.iter().zip(expected.iter()) {
+        assert!((a - e).abs() < 1e-8);
+    }
+}
+
+#[test]
+fn test_dct4_constant_leaks() {
+    let actual = dct4(&[100.0; 32]);
+    for (k, v) in actual.iter().enumerate() {
+        assert_eq!(*v > 0.0, k % 2 == 0, "coefficient {} is {}", k, v);
+    }
+}
+
+#[test]
+fn test_dst2() {
+    let input = vec![0., 1., 2.];
+    let actual = dst2(&input);
+    let expected = [4.00000000e+00, -3.46410162e+00, 2.00000000e+00];
+    assert_eq!(actual.len(), expected.len());
+    for (a, e) in actual.iter().zip(expected.iter()) {
+        assert!((a - e).abs() < 1e-8);
+    }
+}
+
+#[test]
+fn test_adjust_gamma() {
+    let image = image::GrayImage::from_raw(4, 1, vec![0, 64, 128, 255]).unwrap();
+    assert_eq!(
+        adjust_gamma(image.clone(), 1.0).into_raw(),
+        vec![0, 64, 128, 255]
+    );
+    assert_eq!(adjust_gamma(image, 2.0).into_raw(), vec![0, 16, 64, 255]);
+}
+
+#[test]
+fn test_dct2_f32() {
+    let input: Vec<f64> = (0..32).map(|i| f64::from((i * 37 + 11) % 256)).collect();
+    let expected = dct2(&input);
+    let actual = dct2_f32(&input);
+    assert_eq!(actual.len(), expected.len());
+    for (a, e) in actual.iter().zip(&expected) {
+        assert!((a - e).abs() < 0.01, "{} != {}", a, e);
+    }
+}
+
+#[cfg(feature = "fixed-dct")]
+#[test]
+fn test_fixed_dct2() {
+    // power of two, even with an odd factor, and odd lengths
+    for n in [32, 64, 24, 7, 1] {
+        let input: Vec<f64> = (0..n).map(|i| f64::from((i * 37 + 11) % 256)).collect();
+        let expected = dct2(&input);
+        let actual = fixed_dct2(&input);
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(&expected) {
+            assert!((a - e).abs() < 0.01, "{}: {} != {}", n, a, e);
+        }
+    }
+    assert!(fixed_dct2(&[]).is_empty());
+}
+
+#[test]
+fn test_bit_distance() {
+    let a: Vec<bool> = (0..130).map(|i| i % 3 == 0).collect();
+    let b: Vec<bool> = (0..130).map(|i| i % 5 == 0).collect();
+    let expected = a.iter().zip(&b).filter(|(x, y)| x != y).count();
+    assert_eq!(bit_distance(&a, &b), expected);
+    assert_eq!(
+        bit_distance(&a[..64], &b[..64]),
+        expected - bit_distance(&a[64..], &b[64..])
+    );
+    assert_eq!(bit_distance(&[], &[]), 0);
+}
+
+#[test]
+fn test_packed_distance() {
+    let a: Vec<bool> = (0..130).map(|i| i % 3 == 0).collect();
+    let b: Vec<bool> = (0..130).map(|i| i % 5 == 0).collect();
+    let expected = a.iter().zip(&b).filter(|(x, y)| x != y).count();
+    assert_eq!(pack_words(&a).len(), 3);
+    assert_eq!(packed_distance(&pack_words(&a), &pack_words(&b)), expected);
+}
+
+#[test]
+fn test_srgb_to_linear_table() {
+    let table = srgb_to_linear_table();
+    assert_eq!(table[0], 0.0);
+    assert!((table[255] - 255.0).abs() < 1e-4);
+    assert!((table[128] - 55.044).abs() < 0.001);
+    assert!(table.windows(2).all(|w| w[0] < w[1]));
+}
+
+#[test]
+fn test_top_k_values() {
+    let values = [3.0, -5.0, 1.0, 5.0, -0.5];
+    assert_eq!(
+        top_k_values(&values, 3),
+        vec![1.0, 1.0, -1.0, 1.0, -1.0, 3.0, -5.0, 0.0, 5.0, 0.0]
+    );
+    assert_eq!(
+        top_k_values(&values, 0),
+        vec![-1.0, -1.0, -1.0, -1.0, -1.0, 0.0, 0.0, 0.0, 0.0, 0.0]
+    );
+}
+
+#[test]
+fn test_stable_resize() {
+    let flat = image::GrayImage::from_pixel(37, 23, image::Luma([91]));
+    let resized = stable_resize(&flat, 8, 8);
+    assert!(resized.pixels().all(|p| p[0] == 91));
+    let ramp = image::GrayImage::from_fn(8, 8, |x, y| image::Luma([(x * 30 + y) as u8]));
+    assert_eq!(stable_resize(&ramp, 8, 8), ramp);
+    let upscaled = stable_resize(&ramp, 16, 16);
+    assert_eq!(upscaled.dimensions(), (16, 16));
+    assert!(upscaled.get_pixel(0, 0)[0] < upscaled.get_pixel(15, 0)[0]);
+}
+
+#[test]
+fn test_exclude_dc_only_selection() {
+    let pixels: Vec<u8> = (0..32 * 32)
+        .map(|i| ((i * 37 + i / 32 * 11) % 256) as u8)
+        .collect();
+    let image = GrayscaleImage::new(pixels, 32, 32);
+    let dct_rows: Vec<Vec<f64>> = image
+        .iter_rows()
+        .map(|row| dct2(&row.collect::<Vec<f64>>()))
+        .collect();
+    let select = |indices: Vec<(usize, usize)>| -> Vec<f64> {
+        indices.iter().map(|&(x, y)| dct_rows[y][x]).collect()
+    };
+    let block = |columns: std::ops::Range<usize>| -> Vec<(usize, usize)> {
+        (0..8)
+            .flat_map(|y| columns.clone().map(move |x| (x, y)))
+            .collect()
+    };
+
+    let (values, _) = perceptual_hash_values(&image, &PerceptualHash::new());
+    assert_eq!(values, select(block(1..9)));
+
+    let hasher = PerceptualHash::new().with_exclude_dc_only(true);
+    let (values, _) = perceptual_hash_values(&image, &hasher);
+    assert_eq!(values.len(), 63);
+    assert_eq!(values, select(block(0..8)[1..].to_vec()));
+    let weighted = |n| {
+        PerceptualHash::new()
+            .with_exclude_dc_only(true)
+            .with_weight_matrix(vec![1.0; n])
+            .try_hash_gray(&image)
+    };
+    assert!(weighted(63).is_ok());
+    assert!(matches!(
+        weighted(64),
+        Err(HashError::WeightMismatch(63, 64))
+    ));
+}
+
+#[test]
+fn test_exif_orientation() {
+    let segment = |tiff: &[u8]| -> Vec<u8> {
+        let mut data = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00, 0xFF, 0xE1];
+        data.extend(((tiff.len() + 8) as u16).to_be_bytes());
+        data.extend(b"Exif\0\0");
+        data.extend(tiff);
+        data.extend([0xFF, 0xDA, 0x00, 0x02]);
+        data
+    };
+    let big = [
+        b'M', b'M', 0, 42, 0, 0, 0, 8, 0, 2, 0x01, 0x00, 0, 3, 0, 0, 0, 1, 0, 9, 0, 0, 0x01, 0x12,
+        0, 3, 0, 0, 0, 1, 0, 6, 0, 0, 0, 0, 0, 0,
+    ];
+    assert_eq!(exif_orientation(&segment(&big)), Some(6));
+    let little = [
+        b'I', b'I', 42, 0, 8, 0, 0, 0, 1, 0, 0x12, 0x01, 3, 0, 1, 0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    assert_eq!(exif_orientation(&segment(&little)), Some(8));
+    assert_eq!(exif_orientation(&segment(&big[..20])), None);
+    assert_eq!(exif_orientation(&segment(b"XX")), None);
+    assert_eq!(
+        exif_orientation(&[0xFF, 0xD8, 0xFF, 0xDA, 0x00, 0x02]),
+        None
+    );
+    assert_eq!(exif_orientation(b"not a jpeg"), None);
+}
+
+#[test]
+fn test_orientation_from_exif() {
+    let image = image::DynamicImage::ImageLuma8(image::GrayImage::from_fn(3, 2, |x, y| {
+        image::Luma([(y * 3 + x) as u8])
+    }));
+    let pixels = |orientation| {
+        Orientation::from_exif(orientation)
+            .unwrap()
+            .apply(&image)
+            .into_luma8()
+    };
+    assert_eq!(pixels(1).into_raw(), vec![0, 1, 2, 3, 4, 5]);
+    assert_eq!(pixels(2).into_raw(), vec![2, 1, 0, 5, 4, 3]);
+    assert_eq!(pixels(3).into_raw(), vec![5, 4, 3, 2, 1, 0]);
+    assert_eq!(pixels(4).into_raw(), vec![3, 4, 5, 0, 1, 2]);
+    assert_eq!(pixels(5).into_raw(), vec![0, 3, 1, 4, 2, 5]);
+    assert_eq!(pixels(6).into_raw(), vec![3, 0, 4, 1, 5, 2]);
+    assert_eq!(pixels(7).into_raw(), vec![5, 2, 4, 1, 3, 0]);
+    assert_eq!(pixels(8).into_raw(), vec![2, 5, 1, 4, 0, 3]);
+    assert_eq!(pixels(6).dimensions(), (2, 3));
+    assert_eq!(Orientation::from_exif(0), None);
+    assert_eq!(Orientation::from_exif(9), None);
+}
+
+#[test]
+fn test_posterize() {
+    let ramp = image::GrayImage::from_fn(256, 1, |x, _| image::Luma([x as u8]));
+    let levels = |image: image::GrayImage| {
+        let mut values = image.into_raw();
+        values.dedup();
+        values
+    };
+    assert_eq!(levels(posterize(ramp.clone(), 4)), vec![0, 85, 170, 255]);
+    assert_eq!(levels(posterize(ramp.clone(), 1)), vec![0, 255]);
+    assert_eq!(posterize(ramp.clone(), 4).get_pixel(42, 0)[0], 0);
+    assert_eq!(posterize(ramp.clone(), 4).get_pixel(43, 0)[0], 85);
+    assert_eq!(levels(posterize(ramp, 255)).len(), 255);
+}
+
+#[test]
+fn test_block_means() {
+    let image = image::GrayImage::from_fn(5, 2, |x, _| image::Luma([x as u8 * 10]));
+    let result = block_means(&image, 2, 1);
+    assert_eq!(result.pixels, vec![5, 30]);
+    let result = block_means(&image, 5, 2);
+    assert_eq!(result.pixels, image.into_raw());
+}
+
+#[test]
+fn test_center_weights() {
+    let weights = center_weights(3, 3, 3.0);
+    assert_eq!(weights[4], 3.0);
+    for &corner in &[0, 2, 6, 8] {
+        assert!((weights[corner] - 1.0).abs() < 1e-12);
+    }
+    assert!((weights[1] - (3.0 - 2.0 / 2f64.sqrt())).abs() < 1e-12);
+    assert_eq!(center_weights(2, 1, 1.0), vec![1.0, 1.0]);
+}
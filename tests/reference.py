@@ -0,0 +1,47 @@
+# Copyright 2024 Shun Takebayashi
+#
+# Licensed under the Apache License, Version 2.0 (the "License");
+# you may not use this file except in compliance with the License.
+# You may obtain a copy of the License at
+#
+#     http://www.apache.org/licenses/LICENSE-2.0
+#
+# Unless required by applicable law or agreed to in writing, software
+# distributed under the License is distributed on an "AS IS" BASIS,
+# WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+# See the License for the specific language governing permissions and
+# limitations under the License.
+
+# Prints reference hashes computed by the Python imagehash library.
+#
+# Usage: python tests/reference.py > tests/reference.txt
+
+import imagehash
+from PIL import Image
+
+ALGORITHMS = {
+    "ahash": imagehash.average_hash,
+    "dhash": imagehash.dhash,
+    "phash": imagehash.phash,
+    "phash_simple": imagehash.phash_simple,
+}
+
+# Each image is grayscale at the size its algorithms resize to, which Pillow returns unchanged.
+FIXTURES = [
+    ("tests/reference/ahash_8.pgm", "ahash", 8),
+    ("tests/reference/ahash_5.pgm", "ahash", 5),
+    ("tests/reference/dhash_8.pgm", "dhash", 8),
+    ("tests/reference/dhash_5.pgm", "dhash", 5),
+    ("tests/reference/phash_8.pgm", "phash", 8),
+    ("tests/reference/phash_8.pgm", "phash_simple", 8),
+    ("tests/reference/phash_6.pgm", "phash", 6),
+    ("tests/reference/phash_6.pgm", "phash_simple", 6),
+]
+
+print("# Reference hashes of the Python imagehash library.")
+print('# Each line is "<image path> <algorithm> <hash size> <hex hash>", with the hex hash as printed by')
+print("# the library. The images are grayscale at the sizes the algorithms resize to, so neither library")
+print("# resamples them. Regenerate with tests/reference.py.")
+for path, name, hash_size in FIXTURES:
+    hash = ALGORITHMS[name](Image.open(path), hash_size=hash_size)
+    print(path, name, hash_size, hash)
@@ -55,3 +55,2056 @@ fn test_perceptual_hash_2() {
     let result = PerceptualHash::new().hash(&dynimg);
     assert_eq!(result.to_string(), "3f3f3f4c4c4c4c4c");
 }
+
+#[test]
+fn test_hash_from_iterator() {
+    let hash: Hash = (0..16).map(|i| i % 3 == 0).collect();
+    assert_eq!(hash.bits.len(), 16);
+    assert_eq!(hash.to_string(), "9249");
+}
+
+#[test]
+fn test_perceptual_hash_transform() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let default = PerceptualHash::new().hash(&dynimg);
+    let dct2 = PerceptualHash::new()
+        .with_transform(TransformKind::Dct2)
+        .hash(&dynimg);
+    assert_eq!(dct2.to_string(), default.to_string());
+    let dct4 = PerceptualHash::new()
+        .with_transform(TransformKind::Dct4)
+        .hash(&dynimg);
+    // the DCT-IV has no DC coefficient, so the row means dominate with alternating signs
+    assert_eq!(dct4.to_string(), "5555555555555555");
+    let dst2 = PerceptualHash::new()
+        .with_transform(TransformKind::Dst2)
+        .hash(&dynimg);
+    assert_eq!(dst2.to_string(), "5455555555555555");
+}
+
+#[test]
+fn test_distance() {
+    let a: Hash = vec![true, false, true, false].into();
+    let b: Hash = vec![true, true, false, false].into();
+    let c: Hash = vec![true, true].into();
+    assert_eq!(a.distance(&a).unwrap(), Distance(0));
+    assert_eq!(a.distance(&b).unwrap(), Distance(2));
+    assert!(matches!(
+        a.distance(&c),
+        Err(HashError::LengthMismatch(4, 2))
+    ));
+    let d = a.distance(&b).unwrap();
+    assert_eq!(d.to_string(), "2");
+    assert_eq!(d.normalized(4), 0.5);
+    assert!(Distance(1) < d);
+}
+
+#[test]
+fn test_jaccard() {
+    let a = Hash::from_bit_str("11001010").unwrap();
+    let b = Hash::from_bit_str("10001011").unwrap();
+    assert_eq!(a.jaccard(&b).unwrap(), 0.6);
+    assert_eq!(a.jaccard(&a).unwrap(), 1.0);
+    let zero = Hash::from_bit_str("00000000").unwrap();
+    assert_eq!(zero.jaccard(&zero).unwrap(), 1.0);
+    assert_eq!(zero.jaccard(&a).unwrap(), 0.0);
+    assert!(matches!(
+        a.jaccard(&Hash::from_bit_str("1100").unwrap()),
+        Err(HashError::LengthMismatch(8, 4))
+    ));
+}
+
+#[test]
+fn test_count_within() {
+    let query: Hash = vec![false; 8].into();
+    let haystack: Vec<Hash> = vec![
+        vec![false; 8].into(),
+        (0..8).map(|i| i == 0).collect(),
+        (0..8).map(|i| i < 3).collect(),
+        vec![false; 4].into(),
+    ];
+    assert_eq!(count_within(&query, &haystack, Distance(0)), 1);
+    assert_eq!(count_within(&query, &haystack, Distance(1)), 2);
+    assert_eq!(count_within(&query, &haystack, Distance(8)), 3);
+}
+
+#[test]
+fn test_try_hash_low_contrast() {
+    let flat =
+        image::DynamicImage::ImageLuma8(image::GrayImage::from_pixel(64, 64, image::Luma([128])));
+    assert!(matches!(
+        AverageHash::new().try_hash(&flat),
+        Err(HashError::LowContrast)
+    ));
+    assert!(matches!(
+        DifferenceHash::new().try_hash(&flat),
+        Err(HashError::LowContrast)
+    ));
+    assert!(matches!(
+        PerceptualHash::new().try_hash(&flat),
+        Err(HashError::LowContrast)
+    ));
+}
+
+#[test]
+fn test_try_hash_1() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let result = AverageHash::new().try_hash(&dynimg).unwrap();
+    assert_eq!(result.to_string(), "00007cf0e0eafefe");
+}
+
+#[test]
+fn test_hash_u128() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let hash = AverageHash::new().hash(&dynimg);
+    let value = hash.to_u128().unwrap();
+    assert_eq!(value, 0x00007cf0e0eafefe);
+    let restored = Hash::from_u128(value, 64).unwrap();
+    assert_eq!(restored.bits, hash.bits);
+    let short = Hash::from_u128(0b101, 3).unwrap();
+    assert_eq!(short.bits, vec![true, false, true]);
+    assert!(Hash::from(vec![false; 129]).to_u128().is_none());
+    assert!(Hash::from_u128(0, 129).is_none());
+}
+
+#[test]
+fn test_reference_hashes() {
+    let fixtures = std::fs::read_to_string("tests/reference.txt").unwrap();
+    let entries: Vec<&str> = fixtures
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect();
+    assert!(!entries.is_empty());
+    for line in entries {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let (path, algorithm, expected) = (fields[0], fields[1], fields[3]);
+        let n: usize = fields[2].parse().unwrap();
+        let dynimg = image::open(path).unwrap();
+        let result = match algorithm {
+            "ahash" => {
+                let hasher = AverageHash::new()
+                    .with_square_image_size(n)
+                    .with_square_hash_size(n)
+                    .with_python_compat(true);
+                hasher.format_hash(&hasher.hash(&dynimg))
+            }
+            "dhash" => {
+                let hasher = DifferenceHash::new()
+                    .with_image_size(n + 1, n)
+                    .with_square_hash_size(n)
+                    .with_python_compat(true);
+                hasher.format_hash(&hasher.hash(&dynimg))
+            }
+            "phash" | "phash_simple" => {
+                let hasher = if algorithm == "phash" {
+                    PerceptualHash::two_dimensional()
+                } else {
+                    PerceptualHash::simple()
+                };
+                let hasher = hasher
+                    .with_square_image_size(n * 4)
+                    .with_square_hash_size(n)
+                    .with_python_compat(true);
+                hasher.format_hash(&hasher.hash(&dynimg))
+            }
+            _ => panic!("unknown algorithm: {}", algorithm),
+        };
+        assert_eq!(result, expected, "{} {}", path, algorithm);
+    }
+}
+
+#[test]
+fn test_perceptual_hash_zigzag() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let result = PerceptualHash::new().with_zigzag(true).hash(&dynimg);
+    assert_eq!(result.to_string(), "8029fad84f59ff9d");
+}
+
+#[test]
+fn test_hash_to_image() {
+    let hash: Hash = vec![true, false, false, true, true, false].into();
+    let img = hash.to_image(3, 2).unwrap();
+    assert_eq!(img.dimensions(), (3, 2));
+    assert_eq!(img.into_raw(), vec![255, 0, 0, 255, 255, 0]);
+    assert!(hash.to_image(2, 2).is_none());
+}
+
+#[test]
+fn test_perceptual_hash_median() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let mean = PerceptualHash::new().with_pivot(Pivot::Mean).hash(&dynimg);
+    assert_eq!(mean.to_string(), "2f2fafafafafafaf");
+    let median = PerceptualHash::new()
+        .with_pivot(Pivot::Median)
+        .hash(&dynimg);
+    assert_eq!(median.bits.iter().filter(|&&bit| bit).count(), 32);
+    assert_eq!(median.to_string(), "0707ab8b8b8babaa");
+}
+
+#[test]
+fn test_hash_path() {
+    let result = average_hash_path("tests/1.jpg").unwrap();
+    assert_eq!(result.to_string(), "00007cf0e0eafefe");
+    let result = difference_hash_path("tests/1.jpg").unwrap();
+    assert_eq!(result.to_string(), "e0e0f0c4c6d290c0");
+    let result = perceptual_hash_path("tests/1.jpg").unwrap();
+    assert_eq!(result.to_string(), "2f2fafafafafafaf");
+    assert!(matches!(
+        average_hash_path("tests/missing.jpg"),
+        Err(HashError::Decode(_))
+    ));
+}
+
+#[test]
+fn test_open_image_limits() {
+    let dynimg = open_image("tests/1.jpg", default_limits()).unwrap();
+    assert_eq!(
+        AverageHash::new().hash(&dynimg).to_string(),
+        "00007cf0e0eafefe"
+    );
+    let mut limits = default_limits();
+    limits.max_image_width = Some(16);
+    assert!(matches!(
+        open_image("tests/1.jpg", limits),
+        Err(HashError::TooLarge)
+    ));
+    let mut limits = default_limits();
+    limits.max_alloc = Some(1024);
+    assert!(matches!(
+        open_image("tests/1.jpg", limits),
+        Err(HashError::TooLarge)
+    ));
+}
+
+#[test]
+fn test_hash_describe() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let result = AverageHash::new().hash(&dynimg);
+    assert_eq!(result.describe(), "64-bit: 00007cf0e0eafefe");
+    let short: Hash = vec![true; 60].into();
+    assert_eq!(short.describe(), "60-bit: fffffffffffffff0");
+}
+
+#[test]
+fn test_hash_with() {
+    let dynimg = image::open("tests/2.jpg").unwrap();
+    let result = hash_with(Algorithm::Average, &dynimg);
+    assert_eq!(result.to_string(), "fff7e7e3c3000000");
+    let result = hash_with(Algorithm::Difference, &dynimg);
+    assert_eq!(result.to_string(), "ededcc860b0c19b6");
+    let result = hash_with(Algorithm::Perceptual, &dynimg);
+    assert_eq!(result.to_string(), "3f3f3f4c4c4c4c4c");
+}
+
+#[test]
+fn test_aspect_policy_crop_square() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let (width, height) = (dynimg.width(), dynimg.height());
+    assert_ne!(width, height);
+    let side = width.min(height);
+    let cropped = dynimg.crop_imm((width - side) / 2, (height - side) / 2, side, side);
+    let expected = AverageHash::new().hash(&cropped);
+    let result = AverageHash::new()
+        .with_aspect_policy(AspectPolicy::CropSquare)
+        .hash(&dynimg);
+    assert_eq!(result.to_string(), expected.to_string());
+    let result = AverageHash::new()
+        .with_aspect_policy(AspectPolicy::Stretch)
+        .hash(&dynimg);
+    assert_eq!(result.to_string(), "00007cf0e0eafefe");
+}
+
+#[test]
+fn test_majority_hash() {
+    let hashes: Vec<Hash> = vec![
+        vec![true, true, false, false].into(),
+        vec![true, false, true, false].into(),
+        vec![true, true, true, false].into(),
+        vec![false, true, false, false].into(),
+    ];
+    let result = majority_hash(&hashes).unwrap();
+    assert_eq!(result.bits, vec![true, true, false, false]);
+    assert!(matches!(majority_hash(&[]), Err(HashError::Empty)));
+    let mismatched: Vec<Hash> = vec![vec![true; 4].into(), vec![true; 3].into()];
+    assert!(matches!(
+        majority_hash(&mismatched),
+        Err(HashError::LengthMismatch(4, 3))
+    ));
+}
+
+#[test]
+fn test_average_hash_rgba() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let rgba = dynimg.to_rgba8();
+    let result = average_hash_rgba(rgba.as_raw(), rgba.width() as usize, rgba.height() as usize);
+    assert_eq!(result.to_string(), "00007cf0e0eafefe");
+}
+
+fn misbehaving_resizer(
+    image: &image::DynamicImage,
+    width: usize,
+    height: usize,
+) -> image::DynamicImage {
+    image.resize_exact(
+        width as u32 + 1,
+        height as u32,
+        image::imageops::FilterType::Lanczos3,
+    )
+}
+
+#[test]
+fn test_perceptual_hash_unexpected_size() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let result = PerceptualHash::new()
+        .with_resizer(misbehaving_resizer)
+        .try_hash(&dynimg);
+    assert!(matches!(
+        result,
+        Err(HashError::UnexpectedSize((32, 32), (33, 32)))
+    ));
+}
+
+#[test]
+fn test_perceptual_hash_unexpected_size_fallback() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let hasher = PerceptualHash::new().with_resizer(misbehaving_resizer);
+    let hash = hasher.hash(&dynimg);
+    assert_eq!(hash.bits.len(), 64);
+    assert_eq!(hasher.hash_with_confidence(&dynimg).0.bits, hash.bits);
+    assert_eq!(hasher.features(&dynimg).len(), 64);
+}
+
+#[test]
+fn test_matcher() {
+    let img1 = image::open("tests/1.jpg").unwrap();
+    let img2 = image::open("tests/2.jpg").unwrap();
+    let matcher = Matcher::new(Box::new(DifferenceHash::new()), Distance(10));
+    assert_eq!(matcher.hash(&img1).to_string(), "e0e0f0c4c6d290c0");
+    assert!(matcher.matches(&img1, &img1));
+    assert!(!matcher.matches(&img1, &img2));
+}
+
+#[test]
+fn test_histogram_equalization() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let result = AverageHash::new()
+        .with_histogram_equalization(false)
+        .hash(&dynimg);
+    assert_eq!(result.to_string(), "00007cf0e0eafefe");
+    let result = AverageHash::new()
+        .with_histogram_equalization(true)
+        .hash(&dynimg);
+    assert_eq!(result.to_string(), "00007cf8e0eafefe");
+}
+
+#[test]
+fn test_perceptual_hash_non_square() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let result = PerceptualHash::new().with_hash_size(16, 8).hash(&dynimg);
+    assert_eq!(result.bits.len(), 128);
+    assert_eq!(result.to_string(), "2fff0fffafff8bffabff8bffabffabff");
+    let result = PerceptualHash::new().with_hash_size(8, 16).hash(&dynimg);
+    assert_eq!(result.bits.len(), 128);
+    assert_eq!(result.to_string(), "270fab8b8b8bababaaaaab8b8b8b8bcb");
+}
+
+#[test]
+fn test_perceptual_hash_drop_low() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let result = PerceptualHash::new().with_drop_low(1).hash(&dynimg);
+    assert_eq!(result.to_string(), "2f2fafafafafafaf");
+    let result = PerceptualHash::new().with_drop_low(3).hash(&dynimg);
+    assert_eq!(result.bits.len(), 64);
+    assert_eq!(result.to_string(), "1f1fa72f2f2fafab");
+}
+
+#[test]
+fn test_best_match() {
+    let query: Hash = vec![false; 8].into();
+    let candidates: Vec<Hash> = vec![
+        (0..8).map(|i| i < 3).collect(),
+        vec![false; 4].into(),
+        (0..8).map(|i| i == 0).collect(),
+        (0..8).map(|i| i == 7).collect(),
+    ];
+    assert_eq!(best_match(&query, &candidates), Some((2, Distance(1))));
+    assert_eq!(best_match(&query, &candidates[..2]), Some((0, Distance(3))));
+    assert_eq!(best_match(&query, &candidates[1..2]), None);
+    assert_eq!(best_match(&query, &[]), None);
+}
+
+#[test]
+fn test_hash_gray() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let resized = dynimg
+        .grayscale()
+        .resize_exact(9, 8, image::imageops::FilterType::Lanczos3)
+        .into_luma8();
+    let block = image::imageops::crop_imm(&resized, 0, 0, 8, 8).to_image();
+    let gray = GrayscaleImage::from_luma(resized.into_raw(), 9, 8).unwrap();
+    let hashers: Vec<Box<dyn ImageHasher>> = vec![
+        Box::new(DifferenceHash::new()),
+        Box::new(AverageHash::new()),
+    ];
+    assert_eq!(hashers[0].hash_gray(&gray).to_string(), "e0e0f0c4c6d290c0");
+    assert_eq!(hashers[1].hash_gray(&gray).bits.len(), 64);
+    assert_eq!(hashers[1].hash_gray(&gray).to_string(), "00007cf8f0e8fefe");
+    assert_eq!(
+        hashers[1]
+            .hash_gray(&GrayscaleImage::from(block))
+            .to_string(),
+        "00007cf8f0e8fefe"
+    );
+    assert!(GrayscaleImage::from_luma(vec![0; 10], 3, 3).is_none());
+}
+
+#[test]
+fn test_perceptual_hash_resize_filter() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let result = PerceptualHash::new()
+        .with_resize_filter(image::imageops::FilterType::Lanczos3)
+        .hash(&dynimg);
+    assert_eq!(result.to_string(), "2f2fafafafafafaf");
+    let result = PerceptualHash::new()
+        .with_resize_filter(image::imageops::FilterType::Triangle)
+        .hash(&dynimg);
+    assert_eq!(result.to_string(), "3f2fafafafafafaf");
+}
+
+#[test]
+fn test_correlation() {
+    let a: Hash = (0..64).map(|i| i < 32).collect();
+    let inverted: Hash = a.bits.iter().map(|&bit| !bit).collect();
+    assert!((correlation(&a, &a, 8, 8).unwrap() - 1.0).abs() < 1e-9);
+    assert!((correlation(&a, &inverted, 8, 8).unwrap() + 1.0).abs() < 1e-9);
+    let flip = |indices: &[usize]| -> Hash {
+        a.bits
+            .iter()
+            .enumerate()
+            .map(|(i, &bit)| bit != indices.contains(&i))
+            .collect()
+    };
+    let clustered = flip(&[42, 43, 50, 51]);
+    let scattered = flip(&[41, 46, 58, 62]);
+    assert_eq!(a.distance(&clustered).unwrap(), Distance(4));
+    assert_eq!(a.distance(&scattered).unwrap(), Distance(4));
+    assert!(
+        correlation(&a, &clustered, 8, 8).unwrap() < correlation(&a, &scattered, 8, 8).unwrap()
+    );
+    assert!(matches!(
+        correlation(&a, &a, 4, 4),
+        Err(HashError::LengthMismatch(16, 64))
+    ));
+}
+
+#[test]
+fn test_hash_with_confidence() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let (result, score) = AverageHash::new().hash_with_confidence(&dynimg);
+    assert_eq!(result.to_string(), "00007cf0e0eafefe");
+    assert!(score > 0.1 && score <= 1.0);
+    let (result, _) = DifferenceHash::new().hash_with_confidence(&dynimg);
+    assert_eq!(result.to_string(), "e0e0f0c4c6d290c0");
+    let (result, _) = PerceptualHash::new().hash_with_confidence(&dynimg);
+    assert_eq!(result.to_string(), "2f2fafafafafafaf");
+    let flat =
+        image::DynamicImage::ImageLuma8(image::GrayImage::from_pixel(64, 64, image::Luma([128])));
+    let (_, score) = AverageHash::new().hash_with_confidence(&flat);
+    assert_eq!(score, 0.0);
+}
+
+#[test]
+fn test_hash_compact_bytes() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let hash = AverageHash::new().hash(&dynimg);
+    let bytes = hash.to_compact_bytes();
+    assert_eq!(
+        bytes,
+        vec![64, 0x00, 0x00, 0x7c, 0xf0, 0xe0, 0xea, 0xfe, 0xfe]
+    );
+    assert_eq!(Hash::from_compact_bytes(&bytes).unwrap().bits, hash.bits);
+    let long: Hash = (0..200).map(|i| i % 7 == 0).collect();
+    let bytes = long.to_compact_bytes();
+    assert_eq!(&bytes[..2], &[0xc8, 0x01]);
+    assert_eq!(Hash::from_compact_bytes(&bytes).unwrap().bits, long.bits);
+    let odd: Hash = vec![true, false, true].into();
+    assert_eq!(odd.to_compact_bytes(), vec![3, 0xa0]);
+    assert_eq!(Hash::from_compact_bytes(&[3, 0xa0]).unwrap().bits, odd.bits);
+    assert_eq!(
+        Hash::from_compact_bytes(&[]).unwrap_err(),
+        ParseHashError::Truncated
+    );
+    assert_eq!(
+        Hash::from_compact_bytes(&[16, 0xff]).unwrap_err(),
+        ParseHashError::Truncated
+    );
+    assert_eq!(
+        Hash::from_compact_bytes(&[8, 0xff, 0xff]).unwrap_err(),
+        ParseHashError::TrailingBytes
+    );
+    assert_eq!(
+        Hash::from_compact_bytes(&[0xff; 11]).unwrap_err(),
+        ParseHashError::InvalidLength
+    );
+}
+
+#[test]
+fn test_standardize() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let result = AverageHash::new().with_standardize(false).hash(&dynimg);
+    assert_eq!(result.to_string(), "00007cf0e0eafefe");
+    let hasher = AverageHash::new().with_standardize(true);
+    let darker = dynimg.brighten(-30);
+    assert_eq!(
+        hasher.hash(&darker).to_string(),
+        hasher.hash(&dynimg).to_string()
+    );
+}
+
+#[test]
+fn test_distance_histogram() {
+    let zero: Hash = vec![false; 4].into();
+    let pairs: Vec<(Hash, Hash)> = vec![
+        (zero.bits.clone().into(), vec![false; 4].into()),
+        (
+            zero.bits.clone().into(),
+            vec![true, false, true, false].into(),
+        ),
+        (
+            zero.bits.clone().into(),
+            vec![false, true, true, false].into(),
+        ),
+    ];
+    assert_eq!(distance_histogram(&pairs).unwrap(), vec![1, 0, 2]);
+    assert!(distance_histogram(&[]).unwrap().is_empty());
+    let mismatched = vec![(zero, vec![false; 3].into())];
+    assert!(matches!(
+        distance_histogram(&mismatched),
+        Err(HashError::LengthMismatch(4, 3))
+    ));
+}
+
+#[test]
+fn test_perceptual_hash_transpose_output() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let hash = PerceptualHash::new().hash(&dynimg);
+    let transposed = PerceptualHash::new()
+        .with_transpose_output(true)
+        .hash(&dynimg);
+    for y in 0..8 {
+        for x in 0..8 {
+            assert_eq!(transposed.bits[x * 8 + y], hash.bits[y * 8 + x]);
+        }
+    }
+}
+
+#[test]
+fn test_hash_bits() {
+    let hash: Hash = vec![true, false, true].into();
+    assert_eq!(hash.as_bits(), &[true, false, true]);
+    assert_eq!(hash.into_bits(), vec![true, false, true]);
+}
+
+#[test]
+fn test_hash_bit_str() {
+    let hash = Hash::from_bit_str("1011 0000\n0000 0001").unwrap();
+    assert_eq!(hash.to_string(), "b001");
+    assert_eq!(hash.to_binary_string(), "1011000000000001");
+    assert_eq!(Hash::from_bit_str("").unwrap().bits.len(), 0);
+    assert_eq!(
+        Hash::from_bit_str("10x1").unwrap_err(),
+        ParseHashError::InvalidCharacter('x')
+    );
+}
+
+#[test]
+fn test_average_hash_adaptive() {
+    let gradient = image::DynamicImage::ImageLuma8(image::GrayImage::from_fn(64, 64, |x, y| {
+        let stripe = if (y / 8) % 2 == 0 { 0 } else { 24 };
+        image::Luma([(x * 3) as u8 + stripe])
+    }));
+    let global = AverageHash::new().hash(&gradient);
+    assert_eq!(global.to_string(), "0f0f0f0f0f0f0f0f");
+    let adaptive = AverageHash::new().with_adaptive(3).hash(&gradient);
+    assert_eq!(adaptive.to_string(), "017f017f017f017f");
+}
+
+#[test]
+#[should_panic]
+fn test_average_hash_adaptive_zero_block() {
+    AverageHash::new().with_adaptive(0);
+}
+
+#[test]
+fn test_hash_gray_u16() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let resized = dynimg
+        .grayscale()
+        .resize_exact(32, 32, image::imageops::FilterType::Lanczos3)
+        .into_luma8();
+    let wide: Vec<u16> = resized
+        .as_raw()
+        .iter()
+        .map(|&v| u16::from(v) * 257)
+        .collect();
+    let narrow = GrayscaleImage::from_luma(resized.into_raw(), 32, 32).unwrap();
+    let wide = GrayscaleImage::from_luma(wide, 32, 32).unwrap();
+    let hasher = PerceptualHash::new();
+    assert_eq!(hasher.hash_gray(&narrow).to_string(), "2f2fafafafafafaf");
+    assert_eq!(hasher.hash_gray(&wide).to_string(), "2f2fafafafafafaf");
+    let wide = image::ImageBuffer::<image::Luma<u16>, Vec<u16>>::from_pixel(2, 2, image::Luma([1]));
+    let wide: GrayscaleImage<u16> = wide.into();
+    assert_eq!(AverageHash::new().hash_gray(&wide).bits.len(), 4);
+}
+
+#[test]
+fn test_with_preprocess() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let result = DifferenceHash::new()
+        .with_preprocess(Preprocess::new())
+        .hash(&dynimg);
+    assert_eq!(result.to_string(), "e0e0f0c4c6d290c0");
+    let preprocess = Preprocess::new()
+        .with_crop(100, 100, 400, 300)
+        .with_gamma(2.2)
+        .with_blur(1.5);
+    let result = DifferenceHash::new()
+        .with_preprocess(preprocess)
+        .hash(&dynimg);
+    assert_eq!(result.to_string(), "0c6c4cc4c0400000");
+    let first = AverageHash::new()
+        .with_preprocess(Preprocess::new().with_histogram_equalization(true))
+        .hash(&dynimg);
+    let second = AverageHash::new()
+        .with_histogram_equalization(true)
+        .hash(&dynimg);
+    assert_eq!(first.bits, second.bits);
+}
+
+#[test]
+fn test_dct2_f32_parity() {
+    for path in ["tests/1.jpg", "tests/2.jpg", "tests/cmyk.jpg"] {
+        let dynimg = image::open(path).unwrap();
+        let expected = PerceptualHash::new().hash(&dynimg);
+        let actual = PerceptualHash::new()
+            .with_transform(TransformKind::Dct2F32)
+            .hash(&dynimg);
+        assert_eq!(actual.bits, expected.bits);
+    }
+}
+
+#[cfg(feature = "fixed-dct")]
+#[test]
+fn test_fixed_dct2_parity() {
+    for path in ["tests/1.jpg", "tests/2.jpg"] {
+        let dynimg = image::open(path).unwrap();
+        let expected = PerceptualHash::new().hash(&dynimg);
+        let actual = PerceptualHash::new()
+            .with_transform(TransformKind::FixedDct2)
+            .hash(&dynimg);
+        assert_eq!(actual.bits, expected.bits);
+    }
+}
+
+#[test]
+fn test_tagged_hash() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let tagged = perceptual_hash(&dynimg)
+        .tagged(Algorithm::Perceptual, 8, 8)
+        .unwrap();
+    assert_eq!(tagged.to_string(), "phash:8x8:2f2fafafafafafaf");
+    let parsed: TaggedHash = "phash:8x8:2f2fafafafafafaf".parse().unwrap();
+    assert_eq!(parsed.algorithm, Algorithm::Perceptual);
+    assert_eq!((parsed.width, parsed.height), (8, 8));
+    assert_eq!(parsed.distance(&tagged).unwrap(), Distance(0));
+    let parsed: TaggedHash = "dhash:3x3:a980".parse().unwrap();
+    assert_eq!(parsed.hash.to_binary_string(), "101010011");
+    assert_eq!(parsed.to_string(), "dhash:3x3:a980");
+
+    let other = average_hash(&dynimg)
+        .tagged(Algorithm::Average, 8, 8)
+        .unwrap();
+    assert!(matches!(
+        tagged.distance(&other),
+        Err(HashError::TagMismatch)
+    ));
+    let resized: TaggedHash = "phash:16x4:2f2fafafafafafaf".parse().unwrap();
+    assert!(matches!(
+        tagged.distance(&resized),
+        Err(HashError::TagMismatch)
+    ));
+    assert!(matches!(
+        perceptual_hash(&dynimg).tagged(Algorithm::Perceptual, 4, 4),
+        Err(HashError::LengthMismatch(64, 16))
+    ));
+    assert!(matches!(
+        perceptual_hash(&dynimg).tagged(Algorithm::Perceptual, usize::MAX, 2),
+        Err(HashError::LengthMismatch(64, usize::MAX))
+    ));
+
+    for (input, error) in [
+        ("2f2fafafafafafaf", ParseHashError::InvalidFormat),
+        ("xhash:8x8:2f2fafafafafafaf", ParseHashError::InvalidFormat),
+        ("phash:8:2f2fafafafafafaf", ParseHashError::InvalidFormat),
+        ("phash:8x8:2f2f", ParseHashError::Truncated),
+        (
+            "phash:8x8:2f2fafafafafafaf00",
+            ParseHashError::TrailingBytes,
+        ),
+        (
+            "phash:8x8:2f2fafafafafafag",
+            ParseHashError::InvalidCharacter('g'),
+        ),
+    ] {
+        assert_eq!(input.parse::<TaggedHash>().unwrap_err(), error);
+    }
+}
+
+#[test]
+fn test_resample() {
+    let hash = Hash::from_bit_str("10 01").unwrap();
+    let upsampled = hash.resample((2, 2), (4, 4));
+    assert_eq!(upsampled.to_binary_string(), "1100110000110011");
+    let downsampled = upsampled.resample((4, 4), (2, 2));
+    assert_eq!(downsampled.bits, hash.bits);
+    let wide = hash.resample((2, 2), (3, 1));
+    assert_eq!(wide.to_binary_string(), "011");
+
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let small = AverageHash::new().hash(&dynimg);
+    let large = AverageHash::new()
+        .with_image_size(16, 16)
+        .with_hash_size(16, 16)
+        .hash(&dynimg);
+    let distance = large.resample((16, 16), (8, 8)).distance(&small).unwrap();
+    assert!(distance <= Distance(8), "{}", distance);
+}
+
+#[test]
+fn test_perceptual_hash_color() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let result = PerceptualHash::new().with_color(true).hash(&dynimg);
+    assert_eq!(result.bits.len(), 192);
+    assert_eq!(
+        result.to_string(),
+        "2f2f2fafabafafaf2f2fafafafafafab2f2f2fafafafafaf"
+    );
+
+    let mut swapped = dynimg.to_rgb8();
+    for pixel in swapped.pixels_mut() {
+        pixel.0.swap(0, 2);
+    }
+    let swapped = image::DynamicImage::ImageRgb8(swapped);
+    let hasher = PerceptualHash::new().with_color(true);
+    assert_ne!(hasher.hash(&swapped).bits, result.bits);
+}
+
+#[test]
+fn test_best_threshold() {
+    let hash = |s: &str| Hash::from_bit_str(s).unwrap();
+    let pairs = vec![
+        (hash("0000"), hash("0000"), true),
+        (hash("0000"), hash("0001"), true),
+        (hash("0000"), hash("0011"), false),
+        (hash("0000"), hash("0111"), true),
+        (hash("0000"), hash("1111"), false),
+    ];
+    let (max_distance, score) = best_threshold(&pairs).unwrap();
+    assert_eq!(max_distance, Distance(3));
+    assert!((score - 6.0 / 7.0).abs() < 1e-9);
+    assert!(matches!(best_threshold(&[]), Err(HashError::Empty)));
+    assert!(matches!(
+        best_threshold(&[(hash("0"), hash("00"), true)]),
+        Err(HashError::LengthMismatch(1, 2))
+    ));
+}
+
+#[test]
+fn test_features() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let features = AverageHash::new().features(&dynimg);
+    assert_eq!(features.len(), 64);
+    assert!(features.iter().sum::<f64>().abs() < 1e-9);
+    let bits: Vec<bool> = features.iter().map(|&v| v > 0.0).collect();
+    assert_eq!(bits, AverageHash::new().hash(&dynimg).bits);
+
+    let features = DifferenceHash::new().features(&dynimg);
+    let bits: Vec<bool> = features.iter().map(|&v| v > 0.0).collect();
+    assert_eq!(bits, DifferenceHash::new().hash(&dynimg).bits);
+
+    let features = PerceptualHash::new().features(&dynimg);
+    assert_eq!(features.len(), 64);
+    let bits: Vec<bool> = features.iter().map(|&v| v > 0.0).collect();
+    assert_eq!(bits, PerceptualHash::new().hash(&dynimg).bits);
+}
+
+#[test]
+fn test_min_source() {
+    let tiny = image::DynamicImage::ImageLuma8(image::GrayImage::from_fn(6, 20, |x, y| {
+        image::Luma([((x * 40 + y * 10) % 256) as u8])
+    }));
+    assert!(AverageHash::new().try_hash(&tiny).is_ok());
+    assert!(matches!(
+        AverageHash::new().with_min_source(8).try_hash(&tiny),
+        Err(HashError::SourceTooSmall(6, 20))
+    ));
+    assert!(matches!(
+        DifferenceHash::new().with_min_source(8).try_hash(&tiny),
+        Err(HashError::SourceTooSmall(6, 20))
+    ));
+    assert!(matches!(
+        PerceptualHash::new().with_min_source(32).try_hash(&tiny),
+        Err(HashError::SourceTooSmall(6, 20))
+    ));
+    assert!(PerceptualHash::new()
+        .with_min_source(6)
+        .try_hash(&tiny)
+        .is_ok());
+}
+
+#[test]
+fn test_cluster() {
+    let hash = |s: &str| Hash::from_bit_str(s).unwrap();
+    let hashes = vec![
+        hash("0000"),
+        hash("1111"),
+        hash("0001"),
+        hash("0011"),
+        hash("1110"),
+        hash("000"),
+    ];
+    assert_eq!(
+        cluster(&hashes, Distance(1)),
+        vec![vec![0, 2, 3], vec![1, 4], vec![5]]
+    );
+    assert_eq!(
+        cluster(&hashes, Distance(0)),
+        vec![vec![0], vec![1], vec![2], vec![3], vec![4], vec![5]]
+    );
+    assert!(cluster(&[], Distance(4)).is_empty());
+}
+
+#[test]
+fn test_merge_indexes() {
+    let hashes = |bits: &[&str]| -> Vec<Hash> {
+        bits.iter()
+            .map(|s| Hash::from_bit_str(s).unwrap())
+            .collect()
+    };
+    let bits = |hashes: Vec<Hash>| -> Vec<String> {
+        hashes.iter().map(|h| h.to_binary_string()).collect()
+    };
+    let a = ["0000", "1111"];
+    let b = ["0001", "0110", "000"];
+    assert_eq!(
+        bits(merge_indexes(hashes(&a), hashes(&b), Distance(1))),
+        vec!["0000", "1111", "0110", "000"]
+    );
+    assert_eq!(merge_indexes(hashes(&a), hashes(&b), Distance(0)).len(), 5);
+    assert_eq!(
+        bits(merge_indexes(Vec::new(), hashes(&b), Distance(4))),
+        vec!["0001", "000"]
+    );
+    assert!(merge_indexes(Vec::new(), Vec::new(), Distance(4)).is_empty());
+}
+
+#[test]
+fn test_perceptual_hash_simple() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let result = PerceptualHash::simple().hash(&dynimg);
+    assert_eq!(result.to_string(), "2f2fafafafafafaf");
+    assert_eq!(
+        result.to_string(),
+        PerceptualHash::new().hash(&dynimg).to_string()
+    );
+}
+
+#[test]
+fn test_perceptual_hash_two_dimensional() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let result = PerceptualHash::two_dimensional().hash(&dynimg);
+    assert_eq!(result.to_string(), "c11e1a616f9cb167");
+    assert_eq!(
+        result.to_string(),
+        PerceptualHash::new()
+            .with_column_transform(true)
+            .with_pivot(Pivot::Median)
+            .with_drop_low(0)
+            .hash(&dynimg)
+            .to_string()
+    );
+    assert_ne!(
+        result.to_string(),
+        PerceptualHash::simple().hash(&dynimg).to_string()
+    );
+}
+
+#[test]
+fn test_average_hash_debug_image() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let debug = AverageHash::new().debug_image(&dynimg);
+    assert_eq!(debug.dimensions(), (256, 128));
+    let hash = AverageHash::new().hash(&dynimg);
+    for (i, bit) in hash.bits.iter().enumerate() {
+        let (x, y) = ((i % 8) as u32, (i / 8) as u32);
+        let pixel = debug.get_pixel(128 + x * 16 + 8, y * 16 + 8);
+        assert_eq!(pixel[0], if *bit { 255 } else { 0 });
+    }
+}
+
+#[test]
+fn test_perceptual_hash_dct_length() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let result = PerceptualHash::new().with_dct_length(32).hash(&dynimg);
+    assert_eq!(result.to_string(), "2f2fafafafafafaf");
+    let result = PerceptualHash::new().with_dct_length(64).hash(&dynimg);
+    assert_eq!(result.to_string(), "8888888888888888");
+    let result = PerceptualHash::new().with_dct_length(16).hash(&dynimg);
+    assert_eq!(result.to_string(), "3f3f3f3f3f3f3f3f");
+}
+
+#[test]
+fn test_linearize() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let result = AverageHash::new().with_linearize(false).hash(&dynimg);
+    assert_eq!(result.to_string(), "00007cf0e0eafefe");
+    let result = AverageHash::new().with_linearize(true).hash(&dynimg);
+    assert_eq!(result.to_string(), "00007cf0e0e8fcfc");
+    let result = PerceptualHash::new()
+        .with_preprocess(Preprocess::new().with_linearize(true))
+        .hash(&dynimg);
+    assert_eq!(result.to_string(), "3fafafafafafafaf");
+}
+
+#[test]
+fn test_eq_ignoring_padding() {
+    let hash = Hash::from_bit_str("101").unwrap();
+    let padded = Hash::from_bit_str("10100000").unwrap();
+    assert!(hash.eq_ignoring_padding(&padded));
+    assert!(padded.eq_ignoring_padding(&hash));
+    assert!(hash.eq_ignoring_padding(&hash));
+    assert!(!hash.eq_ignoring_padding(&Hash::from_bit_str("10100001").unwrap()));
+    assert!(!hash.eq_ignoring_padding(&Hash::from_bit_str("111").unwrap()));
+}
+
+#[test]
+fn test_perceptual_hash_top_k() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let hasher = PerceptualHash::new().with_image_size(8, 8).with_top_k(10);
+    let result = hasher.hash(&dynimg);
+    assert_eq!(result.bits.len(), 2 * 8 * 7);
+    assert_eq!(result.bits[..56].iter().filter(|&&bit| bit).count(), 10);
+    assert_eq!(result.to_string(), "0000040f1320400000040c102040");
+}
+
+#[test]
+fn test_average_hash_median() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let result = AverageHash::new().with_pivot(Pivot::Mean).hash(&dynimg);
+    assert_eq!(result.to_string(), "00007cf0e0eafefe");
+    let result = AverageHash::new().with_pivot(Pivot::Median).hash(&dynimg);
+    assert_eq!(result.bits.iter().filter(|&&bit| bit).count(), 32);
+    assert_eq!(result.to_string(), "0000fcf0e0eafefe");
+}
+
+#[test]
+fn test_load_hashes() {
+    let input = "00007cf0e0eafefe\n\n  e0e0f0c4c6d290c0  \r\n";
+    let hashes = load_hashes(input.as_bytes(), 64).unwrap();
+    assert_eq!(hashes.len(), 2);
+    assert_eq!(hashes[0].to_string(), "00007cf0e0eafefe");
+    assert_eq!(hashes[1].to_string(), "e0e0f0c4c6d290c0");
+
+    let hashes = load_hashes("1a3\n".as_bytes(), 9).unwrap();
+    assert_eq!(hashes[0].to_binary_string(), "110100011");
+
+    assert_eq!(
+        load_hashes("3a3\n".as_bytes(), 9).unwrap_err(),
+        ParseHashError::InvalidLength
+    );
+    assert_eq!(
+        load_hashes("00007cf0\n".as_bytes(), 64).unwrap_err(),
+        ParseHashError::Truncated
+    );
+    assert_eq!(
+        load_hashes("00007cf0e0eafefe0\n".as_bytes(), 64).unwrap_err(),
+        ParseHashError::TrailingBytes
+    );
+    assert_eq!(
+        load_hashes("00007cf0e0eafefz\n".as_bytes(), 64).unwrap_err(),
+        ParseHashError::InvalidCharacter('z')
+    );
+}
+
+#[test]
+fn test_edges() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let result = DifferenceHash::new().with_edges(false).hash(&dynimg);
+    assert_eq!(result.to_string(), "e0e0f0c4c6d290c0");
+    let result = DifferenceHash::new().with_edges(true).hash(&dynimg);
+    assert_eq!(result.to_string(), "0229c9d968c89348");
+}
+
+#[test]
+fn test_non_finite() {
+    let mut pixels: Vec<f32> = (0..64).map(|i| (i * 37 % 64) as f32).collect();
+    let image = GrayscaleImage::from_luma(pixels.clone(), 8, 8).unwrap();
+    assert!(AverageHash::new().try_hash_gray(&image).is_ok());
+    pixels[10] = f32::NAN;
+    let image = GrayscaleImage::from_luma(pixels.clone(), 8, 8).unwrap();
+    assert!(matches!(
+        AverageHash::new().try_hash_gray(&image),
+        Err(HashError::NonFinite)
+    ));
+    pixels[10] = f32::INFINITY;
+    let image = GrayscaleImage::from_luma(pixels, 8, 8).unwrap();
+    assert!(matches!(
+        PerceptualHash::new()
+            .with_image_size(8, 8)
+            .with_hash_size(4, 4)
+            .try_hash_gray(&image),
+        Err(HashError::NonFinite)
+    ));
+    let image = GrayscaleImage::from_luma(vec![1.0f32, 5.0, f32::NAN, 2.0], 2, 2).unwrap();
+    assert!(matches!(
+        DifferenceHash::new()
+            .with_hash_size(1, 2)
+            .try_hash_gray(&image),
+        Err(HashError::NonFinite)
+    ));
+}
+
+#[test]
+fn test_square_sizes() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let square = PerceptualHash::new()
+        .with_square_image_size(64)
+        .with_square_hash_size(16)
+        .hash(&dynimg);
+    let explicit = PerceptualHash::new()
+        .with_image_size(64, 64)
+        .with_hash_size(16, 16)
+        .hash(&dynimg);
+    assert_eq!(square.bits.len(), 256);
+    assert_eq!(square.bits, explicit.bits);
+    let result = AverageHash::new()
+        .with_square_image_size(8)
+        .with_square_hash_size(8)
+        .hash(&dynimg);
+    assert_eq!(result.to_string(), "00007cf0e0eafefe");
+    let result = DifferenceHash::new()
+        .with_square_image_size(8)
+        .with_square_hash_size(7)
+        .hash(&dynimg);
+    assert_eq!(result.bits.len(), 49);
+}
+
+#[test]
+fn test_region_detector() {
+    fn center(image: &image::DynamicImage) -> Option<(u32, u32, u32, u32)> {
+        Some((
+            image.width() / 4,
+            image.height() / 4,
+            image.width() / 2,
+            image.height() / 2,
+        ))
+    }
+    fn nothing(_: &image::DynamicImage) -> Option<(u32, u32, u32, u32)> {
+        None
+    }
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let result = AverageHash::new()
+        .with_region_detector(nothing)
+        .hash(&dynimg);
+    assert_eq!(result.to_string(), "00007cf0e0eafefe");
+    let detected = AverageHash::new()
+        .with_region_detector(center)
+        .hash(&dynimg);
+    let (x, y, w, h) = center(&dynimg).unwrap();
+    let cropped = AverageHash::new().hash(&dynimg.crop_imm(x, y, w, h));
+    assert_eq!(detected.bits, cropped.bits);
+    assert_ne!(detected.bits, result.bits);
+}
+
+#[test]
+fn test_false_positive_rate() {
+    assert!((false_positive_rate(4, Distance(0)) - 1.0 / 16.0).abs() < 1e-12);
+    assert!((false_positive_rate(4, Distance(1)) - 5.0 / 16.0).abs() < 1e-12);
+    assert!((false_positive_rate(4, Distance(2)) - 11.0 / 16.0).abs() < 1e-12);
+    assert_eq!(false_positive_rate(4, Distance(4)), 1.0);
+    assert_eq!(false_positive_rate(4, Distance(10)), 1.0);
+    assert!((false_positive_rate(64, Distance(10)) / 9.982491e-9 - 1.0).abs() < 1e-6);
+    assert!((false_positive_rate(64, Distance(32)) - 0.5497).abs() < 1e-4);
+    assert!((false_positive_rate(1024, Distance(400)) / 1.315021e-12 - 1.0).abs() < 1e-6);
+}
+
+#[test]
+fn test_gray_resizer() {
+    fn lanczos(image: &image::GrayImage, width: usize, height: usize) -> image::GrayImage {
+        image::imageops::resize(
+            image,
+            width as u32,
+            height as u32,
+            image::imageops::FilterType::Lanczos3,
+        )
+    }
+    fn tiny(image: &image::GrayImage, _: usize, _: usize) -> image::GrayImage {
+        image::imageops::resize(image, 4, 4, image::imageops::FilterType::Nearest)
+    }
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let result = AverageHash::new().with_gray_resizer(lanczos).hash(&dynimg);
+    assert_eq!(result.to_string(), "00007cf0e0eafefe");
+    let result = DifferenceHash::new()
+        .with_gray_resizer(lanczos)
+        .hash(&dynimg);
+    assert_eq!(result.to_string(), "e0e0f0c4c6d290c0");
+    let result = PerceptualHash::new()
+        .with_gray_resizer(lanczos)
+        .hash(&dynimg);
+    assert_eq!(result.to_string(), "2f2fafafafafafaf");
+    assert!(matches!(
+        PerceptualHash::new()
+            .with_gray_resizer(tiny)
+            .try_hash(&dynimg),
+        Err(HashError::UnexpectedSize((32, 32), (4, 4)))
+    ));
+    let result = PerceptualHash::new()
+        .with_gray_resizer(tiny)
+        .with_resizer(misbehaving_resizer)
+        .with_resize_filter(image::imageops::FilterType::Lanczos3)
+        .hash(&dynimg);
+    assert_eq!(result.to_string(), "2f2fafafafafafaf");
+}
+
+#[test]
+fn test_search() {
+    let first = image::open("tests/1.jpg").unwrap();
+    let second = image::open("tests/2.jpg").unwrap();
+    let hasher = PerceptualHash::new();
+    let corpus = vec![
+        ("2.jpg".into(), hasher.hash(&second)),
+        ("short".into(), Hash::from_bit_str("0101").unwrap()),
+        ("1.jpg".into(), hasher.hash(&first)),
+        ("1-copy.jpg".into(), hasher.hash(&first)),
+    ];
+    let matches = search(&first, &corpus, &hasher, 2);
+    assert_eq!(
+        matches,
+        vec![
+            ("1.jpg".into(), Distance(0)),
+            ("1-copy.jpg".into(), Distance(0))
+        ]
+    );
+    let matches = search(&first, &corpus, &hasher, 10);
+    assert_eq!(matches.len(), 3);
+    assert_eq!(matches[2].0, std::path::PathBuf::from("2.jpg"));
+    assert!(search(&first, &corpus, &hasher, 0).is_empty());
+}
+
+#[test]
+fn test_thumbnail_resize() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let result = AverageHash::new()
+        .with_thumbnail_resize(false)
+        .hash(&dynimg);
+    assert_eq!(result.to_string(), "00007cf0e0eafefe");
+    let result = AverageHash::new().with_thumbnail_resize(true).hash(&dynimg);
+    assert_eq!(result.to_string(), "000078f8e0fafefe");
+    let result = PerceptualHash::new()
+        .with_thumbnail_resize(true)
+        .with_thumbnail_resize(false)
+        .hash(&dynimg);
+    assert_eq!(result.to_string(), "2f2fafafafafafaf");
+}
+
+#[test]
+fn test_to_bytes_fixed() {
+    let hash = Hash::from_bit_str("1010 1010 1").unwrap();
+    assert_eq!(hash.to_bytes_fixed(2).unwrap(), vec![0xaa, 0x80]);
+    assert_eq!(hash.to_bytes_fixed(4).unwrap(), vec![0xaa, 0x80, 0, 0]);
+    assert!(matches!(
+        hash.to_bytes_fixed(1),
+        Err(HashError::TooLong(2, 1))
+    ));
+}
+
+#[test]
+fn test_canonicalize() {
+    let hash = Hash::from_bit_str("11010").unwrap();
+    assert_eq!(hash.canonicalize().to_binary_string(), "00101");
+    let inverted = Hash::from_bit_str("00101").unwrap();
+    assert_eq!(inverted.canonicalize().to_binary_string(), "00101");
+    let balanced = Hash::from_bit_str("1100").unwrap();
+    assert_eq!(balanced.canonicalize().to_binary_string(), "1100");
+    assert!(Hash::from_bit_str("")
+        .unwrap()
+        .canonicalize()
+        .bits
+        .is_empty());
+}
+
+#[test]
+fn test_sliding_hashes() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let large = dynimg.crop_imm(0, 0, 60, 50);
+    let hasher = DifferenceHash::new();
+    let windows = hasher.sliding_hashes(&large, (20, 20), (10, 15));
+    let positions: Vec<(u32, u32)> = windows.iter().map(|(p, _)| *p).collect();
+    assert_eq!(
+        positions,
+        vec![
+            (0, 0),
+            (10, 0),
+            (20, 0),
+            (30, 0),
+            (40, 0),
+            (0, 15),
+            (10, 15),
+            (20, 15),
+            (30, 15),
+            (40, 15),
+            (0, 30),
+            (10, 30),
+            (20, 30),
+            (30, 30),
+            (40, 30),
+        ]
+    );
+    for ((x, y), hash) in &windows {
+        let expected = hasher.hash(&large.crop_imm(*x, *y, 20, 20));
+        assert_eq!(hash.bits, expected.bits);
+    }
+    assert!(hasher.sliding_hashes(&large, (61, 10), (10, 10)).is_empty());
+}
+
+#[test]
+fn test_perceptual_hash_weight_matrix() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let result = PerceptualHash::new()
+        .with_weight_matrix(vec![1.0; 64])
+        .try_hash(&dynimg)
+        .unwrap();
+    assert_eq!(result.to_string(), "2f2fafafafafafaf");
+    let weights = (0..64)
+        .map(|i| 1.0 / (1.0 + ((i % 8) + (i / 8)) as f64))
+        .collect();
+    let result = PerceptualHash::new()
+        .with_weight_matrix(weights)
+        .hash(&dynimg);
+    assert_eq!(result.to_string(), "2f2fafafbfbfbfbf");
+    for hasher in [
+        PerceptualHash::new().with_weight_matrix(vec![1.0; 63]),
+        PerceptualHash::new()
+            .with_zigzag(true)
+            .with_weight_matrix(vec![1.0; 65]),
+        PerceptualHash::new()
+            .with_hash_size(40, 8)
+            .with_weight_matrix(vec![1.0; 320]),
+    ] {
+        assert!(matches!(
+            hasher.try_hash(&dynimg),
+            Err(HashError::WeightMismatch(_, _))
+        ));
+    }
+    let result = PerceptualHash::new()
+        .with_hash_size(40, 8)
+        .with_weight_matrix(vec![1.0; 31 * 8])
+        .try_hash(&dynimg);
+    assert!(result.is_ok());
+    let result = PerceptualHash::new()
+        .with_zigzag(true)
+        .with_weight_matrix(vec![1.0; 64])
+        .try_hash(&dynimg);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_perceptual_hash_zero_pivot() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let result = PerceptualHash::new().with_zero_pivot(true).hash(&dynimg);
+    assert_eq!(result.to_string(), "00020080818a8a8a");
+    let median = PerceptualHash::new()
+        .with_pivot(Pivot::Median)
+        .with_zero_pivot(true)
+        .hash(&dynimg);
+    assert_eq!(median.to_string(), result.to_string());
+}
+
+#[test]
+fn test_average_hash_non_square() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let result = AverageHash::new()
+        .with_image_size(16, 8)
+        .with_hash_size(16, 8)
+        .hash(&dynimg);
+    assert_eq!(result.bits.len(), 128);
+    assert_eq!(result.to_string(), "000000007ff0ff90fc00f9c4fffcfff8");
+}
+
+#[test]
+fn test_difference_hash_non_square() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let result = DifferenceHash::new()
+        .with_image_size(17, 8)
+        .with_hash_size(16, 8)
+        .hash(&dynimg);
+    assert_eq!(result.bits.len(), 128);
+    assert_eq!(result.to_string(), "fc00fc00ea00f410d03cf31c6b00d800");
+}
+
+#[test]
+fn test_perceptual_hash_non_square_image() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let result = PerceptualHash::new()
+        .with_image_size(64, 32)
+        .with_hash_size(16, 8)
+        .hash(&dynimg);
+    assert_eq!(result.bits.len(), 128);
+    assert_eq!(result.to_string(), "0fff2fffafff8bffabff8bffabffabff");
+}
+
+#[test]
+fn test_grayscale_disabled() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let luma = image::DynamicImage::ImageLuma8(dynimg.to_luma8());
+    assert_eq!(
+        AverageHash::new()
+            .with_grayscale_disabled(true)
+            .hash(&luma)
+            .to_string(),
+        AverageHash::new().hash(&luma).to_string()
+    );
+    assert_eq!(
+        PerceptualHash::new()
+            .with_preprocess(Preprocess::new().with_grayscale_disabled(true))
+            .hash(&luma)
+            .to_string(),
+        "2f2fafafafafafaf"
+    );
+    let result = DifferenceHash::new()
+        .with_grayscale_disabled(true)
+        .hash(&dynimg);
+    assert_eq!(result.to_string(), "e0c0d0c4c6d290c0");
+}
+
+#[test]
+fn test_combined_distance() {
+    let hash = |s: &str| Hash::from_bit_str(s).unwrap();
+    let a = [hash("0000"), hash("00000000")];
+    let b = [hash("0011"), hash("00000001")];
+    let result = combined_distance(&a, &b, &[1.0, 2.0]).unwrap();
+    assert!((result - 0.75).abs() < 1e-12, "{}", result);
+    assert_eq!(combined_distance(&a, &a, &[1.0, 2.0]).unwrap(), 0.0);
+    assert!(matches!(
+        combined_distance(&a, &b[..1], &[1.0, 2.0]),
+        Err(HashError::LengthMismatch(2, 1))
+    ));
+    assert!(matches!(
+        combined_distance(&a, &b, &[1.0]),
+        Err(HashError::LengthMismatch(2, 1))
+    ));
+    assert!(matches!(
+        combined_distance(&a, &[hash("00000001"), hash("0011")], &[1.0, 2.0]),
+        Err(HashError::LengthMismatch(4, 8))
+    ));
+    assert!(matches!(
+        combined_distance(&[], &[], &[]),
+        Err(HashError::Empty)
+    ));
+}
+
+#[test]
+fn test_stable_pipeline() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let result = AverageHash::new().with_stable_pipeline(true).hash(&dynimg);
+    assert_eq!(result.to_string(), "00007cf0e0eafefe");
+    let result = DifferenceHash::new()
+        .with_stable_pipeline(true)
+        .hash(&dynimg);
+    assert_eq!(result.to_string(), "e0e0f0c4c6d290c0");
+    let result = PerceptualHash::new()
+        .with_stable_pipeline(true)
+        .hash(&dynimg);
+    assert_eq!(result.to_string(), "2f2fafafafafafaf");
+    let result = PerceptualHash::new()
+        .with_stable_pipeline(true)
+        .with_stable_pipeline(false)
+        .hash(&dynimg);
+    assert_eq!(result.to_string(), "2f2fafafafafafaf");
+
+    fn inverted(image: &image::DynamicImage) -> image::DynamicImage {
+        let mut image = image.grayscale();
+        image.invert();
+        image
+    }
+    let custom = || AverageHash::new().with_preprocess(Preprocess::new().with_grayscale(inverted));
+    let expected = custom().hash(&dynimg);
+    let result = custom()
+        .with_stable_pipeline(true)
+        .with_stable_pipeline(false)
+        .hash(&dynimg);
+    assert_eq!(result.to_string(), expected.to_string());
+    assert_ne!(result.to_string(), "00007cf0e0eafefe");
+}
+
+#[test]
+fn test_open_jpeg_scaled() {
+    let full = image::open("tests/1.jpg").unwrap();
+    let scaled = open_jpeg_scaled("tests/1.jpg", 8, 8, default_limits()).unwrap();
+    assert!(scaled.width() >= 8 && scaled.height() >= 8);
+    if cfg!(feature = "jpeg") {
+        assert!(scaled.width() < full.width());
+    } else {
+        assert_eq!(
+            (scaled.width(), scaled.height()),
+            (full.width(), full.height())
+        );
+    }
+    let result = PerceptualHash::new().hash_path("tests/1.jpg").unwrap();
+    let expected = if cfg!(feature = "jpeg") {
+        "2f2fafafafafafab"
+    } else {
+        "2f2fafafafafafaf"
+    };
+    assert_eq!(result.to_string(), expected);
+    let result = AverageHash::new()
+        .with_preprocess(Preprocess::new().with_crop(0, 0, 16, 16))
+        .hash_path("tests/1.jpg")
+        .unwrap();
+    assert_eq!(
+        result.to_string(),
+        AverageHash::new()
+            .with_preprocess(Preprocess::new().with_crop(0, 0, 16, 16))
+            .hash(&full)
+            .to_string()
+    );
+    assert!(matches!(
+        DifferenceHash::new().hash_path("tests/missing.jpg"),
+        Err(HashError::Decode(_))
+    ));
+    assert!(matches!(
+        PerceptualHash::new()
+            .with_resizer(misbehaving_resizer)
+            .hash_path("tests/1.jpg"),
+        Err(HashError::UnexpectedSize((32, 32), (33, 32)))
+    ));
+}
+
+#[test]
+fn test_tiered_match() {
+    let a = Hash::from_bit_str("01100101").unwrap();
+    let b = Hash::from_bit_str("11100110").unwrap();
+    assert_eq!(
+        tiered_match(&a, &b, &[Distance(1), Distance(3), Distance(6)]).unwrap(),
+        vec![false, true, true]
+    );
+    assert!(tiered_match(&a, &b, &[]).unwrap().is_empty());
+    assert!(matches!(
+        tiered_match(&a, &Hash::from_bit_str("0110").unwrap(), &[Distance(1)]),
+        Err(HashError::LengthMismatch(8, 4))
+    ));
+}
+
+#[test]
+fn test_changed_bits() {
+    let a = Hash::from_bit_str("01100101").unwrap();
+    let b = Hash::from_bit_str("11100110").unwrap();
+    assert_eq!(changed_bits(&a, &b).unwrap(), vec![0, 6, 7]);
+    assert_eq!(
+        changed_bits(&a, &b).unwrap().len(),
+        a.distance(&b).unwrap().0
+    );
+    assert!(changed_bits(&a, &a).unwrap().is_empty());
+    assert!(matches!(
+        changed_bits(&a, &Hash::from_bit_str("0110").unwrap()),
+        Err(HashError::LengthMismatch(8, 4))
+    ));
+}
+
+#[test]
+fn test_composite_hash() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let result = CompositeHash::new().hash(&dynimg);
+    assert_eq!(result.bits.len(), 128);
+    assert_eq!(result.to_string(), "00007cf0e0eafefee0e0f0c4c6d290c0");
+    let result = CompositeHash::new()
+        .with_average_hash(AverageHash::new().with_hash_size(4, 4))
+        .with_preprocess(Preprocess::new().with_histogram_equalization(true))
+        .hash(&dynimg);
+    let average = AverageHash::new()
+        .with_hash_size(4, 4)
+        .with_histogram_equalization(true)
+        .hash(&dynimg);
+    let difference = DifferenceHash::new()
+        .with_histogram_equalization(true)
+        .hash(&dynimg);
+    assert_eq!(
+        result.to_binary_string(),
+        average.to_binary_string() + &difference.to_binary_string()
+    );
+
+    let average = AverageHash::new().with_image_size(16, 16).hash(&dynimg);
+    let result = CompositeHash::new()
+        .with_average_hash(AverageHash::new().with_image_size(16, 16))
+        .hash(&dynimg);
+    assert_eq!(result.bits.len(), 128);
+    let (head, tail) = result.split_at(64);
+    assert_eq!(head.to_string(), average.to_string());
+    assert_eq!(tail.to_string(), "e0e0f0c4c6d290c0");
+}
+
+#[cfg(feature = "bitvec")]
+#[test]
+fn test_bitvec() {
+    use bitvec::prelude::*;
+    let hash = Hash::from_bit_str("1011000111").unwrap();
+    let bits = hash.to_bitvec();
+    assert_eq!(bits.len(), 10);
+    assert!(bits[0] && !bits[1] && bits[9]);
+    assert_eq!(Hash::from(bits).to_binary_string(), "1011000111");
+    let bits = bitvec![u8, Msb0; 1, 1, 0, 1];
+    assert_eq!(Hash::from(bits).to_binary_string(), "1101");
+}
+
+#[test]
+fn test_hash_batch() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let images: Vec<image::DynamicImage> = (0..16)
+        .map(|i| dynimg.crop_imm(i * 2, i, 48, 40))
+        .chain([image::open("tests/2.jpg").unwrap()])
+        .collect();
+    let hasher = PerceptualHash::new().with_hash_size(16, 16);
+    let expected: Vec<String> = images
+        .iter()
+        .map(|image| hasher.hash(image).to_binary_string())
+        .collect();
+    let hashes: Vec<String> = hash_batch(&hasher, &images)
+        .iter()
+        .map(|hash| hash.to_binary_string())
+        .collect();
+    assert_eq!(hashes, expected);
+    let hashes = hash_batch(&AverageHash::new(), &images);
+    assert_eq!(hashes.len(), images.len());
+    assert_eq!(hashes[16].to_string(), "fff7e7e3c3000000");
+}
+
+#[test]
+fn test_perceptual_hash_exclude_dc_only() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let result = PerceptualHash::new()
+        .with_exclude_dc_only(true)
+        .hash(&dynimg);
+    assert_eq!(result.bits.len(), 63);
+    assert_eq!(result.to_string(), "0101010101010100");
+    let result = PerceptualHash::new()
+        .with_exclude_dc_only(true)
+        .with_weight_matrix(vec![1.0; 63])
+        .try_hash(&dynimg);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_exif_orientation() {
+    let data = std::fs::read("tests/1.jpg").unwrap();
+    let tiff = [
+        b'M', b'M', 0, 42, 0, 0, 0, 8, 0, 1, 0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, 6, 0, 0, 0, 0, 0, 0,
+    ];
+    let mut tagged = vec![0xFF, 0xD8, 0xFF, 0xE1];
+    tagged.extend(((tiff.len() + 8) as u16).to_be_bytes());
+    tagged.extend(b"Exif\0\0");
+    tagged.extend(tiff);
+    tagged.extend(&data[2..]);
+    let path = std::env::temp_dir().join(format!("imagehash-exif-{}.jpg", std::process::id()));
+    std::fs::write(&path, tagged).unwrap();
+
+    let upright = image::open("tests/1.jpg").unwrap().rotate90();
+    assert_eq!(
+        average_hash_path(&path).unwrap().to_string(),
+        average_hash(&upright).to_string()
+    );
+    let hasher = DifferenceHash::new();
+    let scaled = open_jpeg_scaled("tests/1.jpg", 9, 8, default_limits()).unwrap();
+    assert_eq!(
+        hasher.hash_path(&path).unwrap().to_string(),
+        hasher.hash(&scaled.rotate90()).to_string()
+    );
+    assert_eq!(
+        DifferenceHash::new()
+            .with_exif_orientation(false)
+            .hash_path(&path)
+            .unwrap()
+            .to_string(),
+        hasher.hash(&scaled).to_string()
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_average_hash_n() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let result = AverageHashN::<8, 8>::new().hash(&dynimg);
+    assert_eq!(result.to_string(), "00007cf0e0eafefe");
+    assert_eq!(result.distance(&result), Distance(0));
+    let other = AverageHashN::<8, 8>::new().hash(&image::open("tests/2.jpg").unwrap());
+    let expected = AverageHash::new()
+        .hash(&dynimg)
+        .distance(&AverageHash::new().hash(&image::open("tests/2.jpg").unwrap()))
+        .unwrap();
+    assert_eq!(result.distance(&other), expected);
+
+    let result = AverageHashN::<12, 5>::new().hash(&dynimg);
+    let expected = AverageHash::new()
+        .with_image_size(12, 5)
+        .with_hash_size(12, 5)
+        .hash(&dynimg);
+    assert_eq!(result.to_string(), expected.to_string());
+    assert_eq!(
+        Hash::from(result).to_binary_string(),
+        expected.to_binary_string()
+    );
+    assert_eq!(result.bits()[4][11], expected.as_bits()[59]);
+}
+
+#[test]
+fn test_posterize() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let result = AverageHash::new().with_posterize(4).hash(&dynimg);
+    assert_eq!(result.to_string(), "0000006060686020");
+    let result = PerceptualHash::new()
+        .with_preprocess(Preprocess::new().with_posterize(8))
+        .hash(&dynimg);
+    assert_eq!(result.to_string(), "ffffff9f87a5a8aa");
+}
+
+#[test]
+fn test_matches_with_orientation() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let hasher = PerceptualHash::new();
+    assert_eq!(
+        hasher.matches_with_orientation(&dynimg, &dynimg, Distance(0)),
+        Some(Orientation::Normal)
+    );
+    let rotated = dynimg.rotate90();
+    assert_eq!(
+        hasher.matches_with_orientation(&dynimg, &rotated, Distance(4)),
+        Some(Orientation::Rotate270)
+    );
+    let mirrored = dynimg.fliph();
+    assert_eq!(
+        AverageHash::new().matches_with_orientation(&dynimg, &mirrored, Distance(0)),
+        Some(Orientation::Mirror)
+    );
+    let other = image::open("tests/2.jpg").unwrap();
+    assert_eq!(
+        hasher.matches_with_orientation(&dynimg, &other, Distance(4)),
+        None
+    );
+}
+
+#[cfg(feature = "jpeg")]
+#[test]
+fn test_color_space_cmyk_path() {
+    let cmyk = image::open("tests/cmyk.jpg").unwrap();
+    let hasher = AverageHash::new().with_color_space(ColorSpace::Cmyk);
+    let result = hasher.hash_path("tests/cmyk.jpg").unwrap();
+    assert!(result.distance(&hasher.hash(&cmyk)).unwrap() <= Distance(2));
+    let full = hasher.with_native_blocks(true);
+    let result = full.hash_path("tests/cmyk.jpg").unwrap();
+    assert_eq!(result.to_string(), "00007cf8e0fafefe");
+    assert!(result.distance(&full.hash(&cmyk)).unwrap() <= Distance(2));
+    let hasher = PerceptualHash::new().with_color_space(ColorSpace::Cmyk);
+    assert_eq!(
+        hasher.hash_path("tests/1.jpg").unwrap().to_string(),
+        PerceptualHash::new()
+            .hash_path("tests/1.jpg")
+            .unwrap()
+            .to_string()
+    );
+}
+
+#[test]
+fn test_cmyk() {
+    let rgb = image::open("tests/1.jpg").unwrap();
+    let cmyk = image::open("tests/cmyk.jpg").unwrap();
+    assert_eq!(cmyk.color(), image::ColorType::Rgb8);
+    let result = AverageHash::new().hash(&cmyk);
+    assert_eq!(result.to_string(), "00007cf0e0eafefe");
+    let expected = AverageHash::new().hash(&rgb);
+    assert!(result.distance(&expected).unwrap() <= Distance(2));
+    let converted = AverageHash::new()
+        .with_color_space(ColorSpace::Cmyk)
+        .hash(&cmyk);
+    assert!(converted.distance(&result).unwrap() <= Distance(2));
+
+    let pixels = [0, 0, 0, 0, 255, 255, 255, 0, 0, 0, 0, 255, 0, 255, 255, 51];
+    let gray = GrayscaleImage::from_cmyk(&pixels, 2, 2).unwrap();
+    let luma = [255, 0, 0, 43];
+    let expected = GrayscaleImage::from_luma(luma.to_vec(), 2, 2).unwrap();
+    assert_eq!(
+        AverageHash::new()
+            .with_hash_size(2, 2)
+            .hash_gray(&gray)
+            .to_string(),
+        AverageHash::new()
+            .with_hash_size(2, 2)
+            .hash_gray(&expected)
+            .to_string()
+    );
+    assert!(GrayscaleImage::from_cmyk(&pixels, 2, 1).is_none());
+}
+
+#[test]
+fn test_hash_split_at() {
+    let hash = Hash::from_bit_str("1011001").unwrap();
+    let (head, tail) = hash.split_at(3);
+    assert_eq!(head.to_binary_string(), "101");
+    assert_eq!(tail.to_binary_string(), "1001");
+    let (head, tail) = hash.split_at(7);
+    assert_eq!(head.bits.len(), 7);
+    assert!(tail.bits.is_empty());
+
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let (average, difference) = CompositeHash::new().hash(&dynimg).split_at(64);
+    assert_eq!(average.to_string(), "00007cf0e0eafefe");
+    assert_eq!(difference.to_string(), "e0e0f0c4c6d290c0");
+}
+
+#[test]
+#[should_panic]
+fn test_hash_split_at_out_of_range() {
+    Hash::from_bit_str("101").unwrap().split_at(4);
+}
+
+#[test]
+fn test_perceptual_hash_oversample() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let result = PerceptualHash::new().with_oversample(1).hash(&dynimg);
+    assert_eq!(result.to_string(), "2f2fafafafafafaf");
+    let hasher = PerceptualHash::new().with_oversample(4);
+    let result = hasher.hash(&dynimg);
+    assert_eq!(result.to_string(), "2f2fafafafafafab");
+    assert_eq!(
+        hasher.try_hash(&dynimg).unwrap().to_string(),
+        result.to_string()
+    );
+    let single = PerceptualHash::new().hash(&dynimg);
+    let (confident, _) = hasher.hash_with_confidence(&dynimg);
+    assert_eq!(confident.to_string(), single.to_string());
+    let signs: Vec<bool> = hasher.features(&dynimg).iter().map(|&v| v > 0.0).collect();
+    assert_eq!(signs, single.bits);
+}
+
+#[test]
+fn test_average_hash_native_blocks() {
+    let pixels: Vec<u8> = (0..64).map(|i| if i % 3 == 0 { 200 } else { 10 }).collect();
+    let native = image::GrayImage::from_raw(8, 8, pixels.clone()).unwrap();
+    let hasher = AverageHash::new().with_native_blocks(true);
+    let expected = AverageHash::new()
+        .hash_gray(&GrayscaleImage::from(native.clone()))
+        .to_string();
+    let result = hasher.hash(&image::DynamicImage::ImageLuma8(native.clone()));
+    assert_eq!(result.to_string(), expected);
+    let scaled = image::imageops::resize(&native, 24, 16, image::imageops::FilterType::Nearest);
+    let result = hasher.hash(&image::DynamicImage::ImageLuma8(scaled));
+    assert_eq!(result.to_string(), expected);
+
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let result = hasher.hash(&dynimg);
+    assert_eq!(result.to_string(), "000078f8e0fafefe");
+}
+
+#[test]
+fn test_python_compat() {
+    let rgb = image::RgbImage::from_fn(8, 8, |x, y| match (x + y) % 3 {
+        0 => image::Rgb([255, 0, 0]),
+        1 => image::Rgb([0, 0, 255]),
+        _ => image::Rgb([(x * 30) as u8, (y * 30) as u8, 90]),
+    });
+    let luma: Vec<u8> = rgb
+        .pixels()
+        .map(|p| {
+            let l = 19595 * u32::from(p[0]) + 38470 * u32::from(p[1]) + 7471 * u32::from(p[2]);
+            ((l + 0x8000) >> 16) as u8
+        })
+        .collect();
+    let gray = GrayscaleImage::from_luma(luma, 8, 8).unwrap();
+    let hasher = AverageHash::new().with_python_compat(true);
+    let result = hasher.hash(&image::DynamicImage::ImageRgb8(rgb.clone()));
+    assert_eq!(result.bits, hasher.hash_gray(&gray).bits);
+    assert_ne!(
+        result.bits,
+        AverageHash::new()
+            .hash(&image::DynamicImage::ImageRgb8(rgb))
+            .bits
+    );
+
+    let hash = Hash::from_bit_str("110100011").unwrap();
+    assert_eq!(hasher.format_hash(&hash), "1a3");
+    assert_eq!(AverageHash::new().format_hash(&hash), hash.to_string());
+}
+
+#[test]
+fn test_hash_to_python_hex() {
+    let hash = Hash::from_bit_str("110100011").unwrap();
+    assert_eq!(hash.to_python_hex(), "1a3");
+    assert_eq!(hash.to_string(), "d180");
+    let hashes = load_hashes(hash.to_python_hex().as_bytes(), 9).unwrap();
+    assert_eq!(hashes[0].to_binary_string(), "110100011");
+    assert_eq!(Hash::from_bit_str("1011").unwrap().to_python_hex(), "b");
+    assert_eq!(Hash::from_bit_str("").unwrap().to_python_hex(), "");
+
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let result = AverageHash::new().hash(&dynimg);
+    assert_eq!(result.to_python_hex(), result.to_string());
+
+    // formatted by the steps of _binary_array_to_hex in the Python imagehash library
+    let cases = [
+        ("1".to_string(), "1"),
+        ("0000000001".to_string(), "001"),
+        ("10".repeat(37), "2aaaaaaaaaaaaaaaaaa"),
+        (
+            "1".repeat(12) + &"0".repeat(132),
+            "fff000000000000000000000000000000000",
+        ),
+    ];
+    for (bits, expected) in cases.iter() {
+        let hash = Hash::from_bit_str(bits).unwrap();
+        assert_eq!(hash.to_python_hex(), *expected);
+        if hash.bits.len() % 8 == 0 {
+            assert_eq!(hash.to_string(), *expected);
+        }
+    }
+
+    // images already at the image size are not resized by either library, so these follow the
+    // row-major bit order of average_hash and dhash
+    let native = |width| {
+        image::DynamicImage::ImageLuma8(image::GrayImage::from_fn(width, 8, |x, y| {
+            image::Luma([((x * 7 + y * 13 + (x * y) % 17 * 5) % 256) as u8])
+        }))
+    };
+    let result = AverageHash::new().hash(&native(8));
+    assert_eq!(result.to_python_hex(), "0000071d3f3f7f7f");
+    let result = DifferenceHash::new().hash(&native(9));
+    assert_eq!(result.to_python_hex(), "fffffffbf7eddbd6");
+}
+
+#[test]
+fn test_frame_hasher() {
+    let first = image::open("tests/1.jpg").unwrap();
+    let second = image::open("tests/2.jpg").unwrap();
+    let mut frames = FrameHasher::new(Box::new(AverageHash::new())).with_history(3);
+    assert_eq!(frames.push(&first).to_string(), "00007cf0e0eafefe");
+    assert_eq!(frames.last_distance(), None);
+    assert!(!frames.is_scene_change(Distance(0)));
+    frames.push(&first);
+    assert_eq!(frames.last_distance(), Some(Distance(0)));
+    assert!(!frames.is_scene_change(Distance(0)));
+    assert_eq!(frames.push(&second).to_string(), "fff7e7e3c3000000");
+    assert!(frames.is_scene_change(Distance(10)));
+    frames.push(&second);
+    let recent: Vec<String> = frames.recent().map(|h| h.to_string()).collect();
+    assert_eq!(
+        recent,
+        vec!["00007cf0e0eafefe", "fff7e7e3c3000000", "fff7e7e3c3000000"]
+    );
+    frames.reset();
+    assert_eq!(frames.recent().count(), 0);
+    assert_eq!(frames.last_distance(), None);
+}
+
+#[test]
+fn test_diff_bbox() {
+    let a = Hash::from_bit_str("0000 0000 0000").unwrap();
+    let b = Hash::from_bit_str("0000 0010 0100").unwrap();
+    assert_eq!(diff_bbox(&a, &b, 4, 3).unwrap(), Some((1, 1, 2, 2)));
+    assert_eq!(diff_bbox(&a, &a, 4, 3).unwrap(), None);
+    let c = Hash::from_bit_str("1000 0000 0001").unwrap();
+    assert_eq!(diff_bbox(&a, &c, 4, 3).unwrap(), Some((0, 0, 4, 3)));
+    assert!(matches!(
+        diff_bbox(&a, &b, 4, 4),
+        Err(HashError::LengthMismatch(12, 16))
+    ));
+    assert!(matches!(
+        diff_bbox(&a, &Hash::from_bit_str("0").unwrap(), 4, 3),
+        Err(HashError::LengthMismatch(12, 1))
+    ));
+}
+
+#[test]
+fn test_difference_hash_ignore_transparent() {
+    let logo = |hidden: u8| {
+        image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(36, 32, |x, y| {
+            if (8..28).contains(&x) && (8..24).contains(&y) {
+                let v = (x * 12) as u8;
+                image::Rgba([v, v, v, 255])
+            } else {
+                let v = hidden.wrapping_add((x * 37 + y * 11) as u8);
+                image::Rgba([v, v, v, 0])
+            }
+        }))
+    };
+    let (a, b) = (logo(0), logo(128));
+    let hasher = DifferenceHash::new();
+    assert_ne!(hasher.hash(&a).to_string(), hasher.hash(&b).to_string());
+    let hasher = DifferenceHash::new().with_ignore_transparent(true);
+    assert_eq!(hasher.hash(&a).to_string(), hasher.hash(&b).to_string());
+    assert_eq!(
+        hasher.try_hash(&a).unwrap().to_string(),
+        hasher.hash(&a).to_string()
+    );
+
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    assert_eq!(hasher.hash(&dynimg).to_string(), "e0e0f0c4c6d290c0");
+
+    static CALLS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+    fn counted(_: &image::DynamicImage) -> Option<(u32, u32, u32, u32)> {
+        CALLS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        Some((4, 4, 28, 24))
+    }
+    let cropped = hasher.hash(&a.crop_imm(4, 4, 28, 24));
+    let detected = hasher.with_region_detector(counted).hash(&a);
+    assert_eq!(CALLS.load(std::sync::atomic::Ordering::SeqCst), 1);
+    assert_eq!(detected.to_string(), cropped.to_string());
+}
+
+#[test]
+fn test_incremental_average_hash() {
+    let pixels: Vec<u8> = (0..64).map(|i| if i % 3 == 0 { 200 } else { 10 }).collect();
+    let native = image::GrayImage::from_raw(8, 8, pixels.clone()).unwrap();
+    let scaled = image::imageops::resize(&native, 24, 16, image::imageops::FilterType::Nearest);
+    let mut hasher = IncrementalAverageHash::new();
+    assert!(hasher.is_empty());
+    for by in 0..8 {
+        for bx in 0..8 {
+            let mut sum = 0.0;
+            for y in by * 2..by * 2 + 2 {
+                for x in bx * 3..bx * 3 + 3 {
+                    sum += f64::from(scaled.get_pixel(x, y)[0]);
+                }
+            }
+            hasher.add_block(sum, 6);
+        }
+    }
+    assert_eq!(hasher.len(), 64);
+    let expected = AverageHash::new()
+        .with_native_blocks(true)
+        .hash(&image::DynamicImage::ImageLuma8(scaled));
+    assert_eq!(hasher.finish().to_string(), expected.to_string());
+
+    let mut hasher = IncrementalAverageHash::new();
+    hasher.add_block(10.0, 2);
+    hasher.add_block(0.0, 0);
+    hasher.add_block(30.0, 1);
+    assert_eq!(hasher.finish().to_binary_string(), "001");
+    assert!(IncrementalAverageHash::new().finish().bits.is_empty());
+}
+
+#[test]
+fn test_hash_select_bits() {
+    let hash = Hash::from_bit_str("1011001").unwrap();
+    assert_eq!(hash.select_bits(&[0, 2, 3]).to_binary_string(), "111");
+    assert_eq!(hash.select_bits(&[6, 1, 6]).to_binary_string(), "101");
+    assert!(hash.select_bits(&[]).bits.is_empty());
+}
+
+#[test]
+#[should_panic]
+fn test_hash_select_bits_out_of_range() {
+    Hash::from_bit_str("101").unwrap().select_bits(&[3]);
+}
+
+#[test]
+fn test_is_hashable() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    assert!(grayscale_variance(&dynimg) > 100.0);
+    assert!(is_hashable(&dynimg, MIN_VARIANCE));
+    assert!(!is_hashable(&dynimg, f64::INFINITY));
+
+    let flat = image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(
+        16,
+        16,
+        image::Rgb([90, 90, 90]),
+    ));
+    assert_eq!(grayscale_variance(&flat), 0.0);
+    assert!(!is_hashable(&flat, MIN_VARIANCE));
+    assert!(is_hashable(&flat, 0.0));
+
+    let empty = image::DynamicImage::ImageLuma8(image::GrayImage::new(0, 0));
+    assert!(grayscale_variance(&empty).is_nan());
+    assert!(!is_hashable(&empty, 0.0));
+
+    let gray = GrayscaleImage::from_luma(vec![0u8, 2, 0, 2], 2, 2).unwrap();
+    assert_eq!(gray.variance(), 1.0);
+}
+
+#[test]
+fn test_hash_format() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let hash = AverageHash::new().hash(&dynimg);
+    assert_eq!(HashFormat::new().format(&hash), hash.to_string());
+    let format = HashFormat::new().with_prefix("0x");
+    assert_eq!(format.format(&hash), "0x00007cf0e0eafefe");
+    let format = format.with_byte_separator(":");
+    assert_eq!(format.format(&hash), "0x00:00:7c:f0:e0:ea:fe:fe");
+    let hash = Hash::from_bit_str("110100011").unwrap();
+    assert_eq!(
+        HashFormat::new().with_byte_separator("-").format(&hash),
+        "d1-80"
+    );
+    assert_eq!(
+        HashFormat::new()
+            .with_prefix("0x")
+            .format(&Hash::from_bit_str("").unwrap()),
+        "0x"
+    );
+}
+
+#[test]
+fn test_average_hash_center_weight() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let result = AverageHash::new().with_center_weight(1.0).hash(&dynimg);
+    assert_eq!(result.to_string(), "00007cf0e0eafefe");
+    let hasher = AverageHash::new().with_center_weight(4.0);
+    let result = hasher.hash(&dynimg);
+    assert_eq!(result.to_string(), "00007cf0e0e8fefe");
+    assert_eq!(
+        hasher.try_hash(&dynimg).unwrap().to_string(),
+        result.to_string()
+    );
+}
+
+#[test]
+fn test_hash_distances() {
+    let first = image::open("tests/1.jpg").unwrap();
+    let second = image::open("tests/2.jpg").unwrap();
+    let query = average_hash(&first);
+    let others = vec![
+        average_hash(&second),
+        average_hash(&first),
+        Hash::from_bit_str(&"1".repeat(64)).unwrap(),
+    ];
+    let expected: Vec<Distance> = others.iter().map(|o| query.distance(o).unwrap()).collect();
+    assert_eq!(query.distances(&others).unwrap(), expected);
+    assert_eq!(expected[1], Distance(0));
+    assert!(query.distances(&[]).unwrap().is_empty());
+    let mixed = vec![average_hash(&second), Hash::from_bit_str("101").unwrap()];
+    assert!(matches!(
+        query.distances(&mixed),
+        Err(HashError::LengthMismatch(64, 3))
+    ));
+}
+
+#[test]
+fn test_average_hash_image_size_larger_than_hash_size() {
+    let dynimg = image::open("tests/1.jpg").unwrap();
+    let hasher = AverageHash::new()
+        .with_image_size(16, 16)
+        .with_hash_size(8, 8);
+    let result = hasher.hash(&dynimg);
+    assert_eq!(result.bits.len(), 64);
+    assert_eq!(hasher.features(&dynimg).len(), 64);
+    assert_eq!(result.to_string(), "0000001f7fffffff");
+    let result = hasher.with_adaptive(3).hash(&dynimg);
+    assert_eq!(result.bits.len(), 64);
+}